@@ -1,10 +1,18 @@
 use anyhow::{bail, Context, Result};
 use indexmap::IndexMap;
 use std::ops::Add;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
 use tokio_util::sync::CancellationToken;
 
-use crate::database::Database;
+use crate::config::Config;
+use crate::database::{Database, PageRun};
 use crate::page::Page;
+use crate::request::Clients;
+use crate::robots::DEFAULT_USER_AGENT;
+use crate::secret::Secret;
+use crate::snapshot::Snapshots;
 
 #[derive(Debug)]
 pub struct Collection {
@@ -17,31 +25,60 @@ pub struct CollectionStats {
     pub n_pages: u64,
     pub n_links: u64,
     pub n_new_links: u64,
+    pub n_failed_pages: u64,
+}
+
+/// What the page tasks of a collection share.
+#[derive(Debug, Clone)]
+struct Shared {
+    collection_id: i64,
+    database: Database,
+    clients: Clients,
+    snapshots: Option<Snapshots>,
+    cancellation_token: CancellationToken,
 }
 
 impl Collection {
     pub async fn try_new(
-        pages: &[Page],
+        config: &Config,
         database: &Database,
+        clients: &Clients,
         cancellation_token: CancellationToken,
     ) -> Result<Self> {
         let collection_id = database.start_collection().await?;
         let mut counter = IndexMap::new();
         let mut page_tasks = Vec::new();
+        let permits = Arc::new(Semaphore::new(
+            config
+                .limits
+                .max_concurrency
+                .unwrap_or(Semaphore::MAX_PERMITS)
+                .max(1),
+        ));
+
+        let shared = Shared {
+            collection_id,
+            database: database.clone(),
+            clients: clients.clone(),
+            snapshots: config.snapshots.clone(),
+            cancellation_token,
+        };
 
         log::info!("starting collection {collection_id}");
 
-        for page in pages {
+        for page in &config.page {
             counter.insert(page.name.clone(), 0);
 
+            let permits = permits.clone();
+            let task = collect_page(page.clone(), shared.clone());
+
             page_tasks.push((
                 &page.name,
-                tokio::task::spawn(collect_page(
-                    page.clone(),
-                    collection_id,
-                    database.clone(),
-                    cancellation_token.clone(),
-                )),
+                tokio::task::spawn(async move {
+                    let _permit = permits.acquire_owned().await?;
+
+                    task.await
+                }),
             ));
         }
 
@@ -55,6 +92,7 @@ impl Collection {
                         target: page_name,
                         "collection failed: {error}"
                     );
+                    total.n_failed_pages += 1;
                     continue;
                 }
             };
@@ -83,11 +121,10 @@ impl Collection {
                 total.n_pages,
                 total.n_links,
                 total.n_new_links,
+                total.n_failed_pages,
             )
             .await?;
 
-        // TODO Update `is_active` for each record in `links`.
-
         Ok(Self {
             stats: total,
             counter,
@@ -103,59 +140,254 @@ impl Add for CollectionStats {
             n_pages: self.n_pages + other.n_pages,
             n_links: self.n_links + other.n_links,
             n_new_links: self.n_new_links + other.n_new_links,
+            n_failed_pages: self.n_failed_pages + other.n_failed_pages,
         }
     }
 }
 
 async fn collect_page(
     page: Page,
-    collection_id: i64,
-    database: Database,
-    cancellation_token: CancellationToken,
+    shared: Shared,
 ) -> Result<CollectionStats> {
+    let Shared {
+        collection_id,
+        database,
+        ..
+    } = &shared;
+
     let page_id = database.add_page(&page.url, &page.extract).await?;
-    let mut n_links = 0;
-    let mut n_new_links = 0;
 
     log::info!(target: &page.name, "page ID {page_id}");
 
-    for link in page.request(cancellation_token).await?.iter() {
-        let mut is_new = false;
-        n_links += 1;
+    let start_time = Instant::now();
+    let mut run = PageRun::default();
+    let result = collect_links(&page, page_id, &shared, &mut run).await;
 
-        if !database
-            .link_exists(page_id, &link.href, &link.text)
-            .await?
-        {
-            is_new = true;
-            n_new_links += 1;
+    // A cancelled run says nothing about the health of the page, so it
+    // isn't recorded.
+    //
+    if result.is_err() && shared.cancellation_token.is_cancelled() {
+        return result;
+    }
+
+    run.duration_ms = u64::try_from(start_time.elapsed().as_millis())
+        .unwrap_or(u64::MAX);
+
+    if let Err(error) = &result {
+        run.error = Some(format!("{error:#}"));
+    }
+
+    // The page's own error is more useful than a failure to record it.
+    //
+    match database.add_page_run(*collection_id, page_id, run).await {
+        Err(error) if result.is_err() => {
+            log::error!(target: &page.name, "{error:#}");
+        }
+        Err(error) => return Err(error),
+        Ok(_) => {}
+    }
+
+    result
+}
+
+async fn collect_links(
+    page: &Page,
+    page_id: i64,
+    shared: &Shared,
+    run: &mut PageRun,
+) -> Result<CollectionStats> {
+    let collection_id = shared.collection_id;
+    let database = &shared.database;
+    let mut n_new_links = 0;
+
+    // Skipped pages count as failed so that a host that is down shows
+    // up in the health checks.
+    //
+    if !page.ignore_robots {
+        let url = reqwest::Url::parse(&page.url)
+            .with_context(|| format!("URL {:?}", page.url))?;
+        let user_agent = page
+            .request
+            .user_agent
+            .as_deref()
+            .unwrap_or(DEFAULT_USER_AGENT);
+        let rules = shared
+            .clients
+            .robots(
+                &url,
+                user_agent,
+                page.request.proxy.as_ref().map(Secret::expose),
+            )
+            .await?;
+
+        if !rules.is_allowed(&url) {
+            bail!("disallowed by robots.txt");
         }
+    }
 
-        let link_id =
-            database.add_link(page_id, &link.href, &link.text).await?;
+    // The first successful collection of a page would report every
+    // link as new.  With `baseline` set, its links are recorded but
+    // not counted as new so that they don't trigger an alert.
+    //
+    let has_history = database.page_has_history(page_id).await?;
+    let is_first_run = page.baseline && !has_history;
 
-        if is_new {
-            log::info!(
-                target: &page.name,
-                "new link {:?} {:?}",
-                link.href,
-                link.text
-            );
+    if is_first_run {
+        log::info!(target: &page.name, "first run; recording baseline");
+    }
+
+    let validators = if is_first_run {
+        Default::default()
+    } else {
+        database.cache_validators(page_id).await?
+    };
+    let fetched = page
+        .fetch(
+            &validators,
+            &shared.clients,
+            shared.cancellation_token.clone(),
+        )
+        .await?;
+
+    run.http_status = Some(fetched.status);
+    run.n_bytes = Some(fetched.body.len() as u64);
+
+    // A snapshot that cannot be stored is not worth failing the page
+    // for.
+    //
+    if let Some(x) = &shared.snapshots
+        && fetched.status != 304
+        && let Err(error) = x
+            .store(database, collection_id, page_id, &fetched.body)
+            .await
+    {
+        log::error!(target: &page.name, "{error:#}");
+    }
+
+    // If the page hasn't changed, the links observed in the previous
+    // collection are observed again without extracting them.
+    //
+    let is_not_modified = fetched.status == 304;
+    let mut content_hash = None;
+
+    if is_not_modified {
+        log::info!(target: &page.name, "not modified; reusing links");
+    } else if (200..300).contains(&fetched.status) {
+        let hash = page.content_hash(&fetched.body)?;
+
+        if !is_first_run
+            && database.content_hash(page_id).await?.as_ref()
+                == Some(&hash)
+        {
+            log::info!(target: &page.name, "unchanged; reusing links");
         } else {
-            log::info!(
-                target: &page.name,
-                "existing link {:?} {:?}",
-                link.href,
-                link.text
-            );
+            content_hash = Some(hash);
         }
+    } else {
+        bail!("HTTP status {}", fetched.status);
+    }
+
+    let n_links = match content_hash {
+        None => {
+            database.reobserve_links(page_id, collection_id).await?
+        }
+        Some(hash) => {
+            let links = page.extract(&fetched.body)?;
+            let n_links = links.len() as u64;
+
+            for (link, is_new) in database
+                .record_links(page_id, collection_id, links)
+                .await?
+            {
+                if is_first_run {
+                    log::info!(
+                        target: &page.name,
+                        "baseline link {:?} {:?}",
+                        link.href,
+                        link.text
+                    );
+                } else if is_new {
+                    n_new_links += 1;
 
-        database.add_link_collection(link_id, collection_id).await?;
+                    log::info!(
+                        target: &page.name,
+                        "new link {:?} {:?}",
+                        link.href,
+                        link.text
+                    );
+                } else {
+                    log::info!(
+                        target: &page.name,
+                        "existing link {:?} {:?}",
+                        link.href,
+                        link.text
+                    );
+                }
+            }
+
+            database.set_content_hash(page_id, hash).await?;
+            n_links
+        }
+    };
+
+    if !is_not_modified && fetched.validators != validators {
+        database
+            .set_cache_validators(page_id, fetched.validators)
+            .await?;
+    }
+
+    // Recorded even if the page has no links, so that the links that
+    // appear later are new rather than a baseline.
+    //
+    if !has_history {
+        database.set_baselined(page_id).await?;
     }
 
+    run.n_links = Some(n_links);
+    run.n_new_links = Some(n_new_links);
+
     Ok(CollectionStats {
         n_pages: 1,
         n_links,
         n_new_links,
+        n_failed_pages: 0,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn cancelled_runs_are_not_recorded() {
+        let config: Config = toml::from_str(
+            "database = \":memory:\"\n\
+             [[page]]\nname = \"a\"\n\
+             url = \"http://127.0.0.1:9/\"\nextract = \"a\"\n\
+             ignore_robots = true\n",
+        )
+        .unwrap();
+        let database = Database::try_new(":memory:").unwrap();
+        let clients = Clients::new(&config).unwrap();
+        let cancellation_token = CancellationToken::new();
+
+        cancellation_token.cancel();
+
+        let collection = Collection::try_new(
+            &config,
+            &database,
+            &clients,
+            cancellation_token,
+        )
+        .await
+        .unwrap();
+        let page = &config.page[0];
+
+        assert_eq!(collection.stats.n_failed_pages, 1);
+        assert!(database
+            .recent_page_runs(&page.url, &page.extract, 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
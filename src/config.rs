@@ -1,15 +1,56 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::Deserialize;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
+use crate::database::Retention;
+use crate::health::Health;
+use crate::limit::Limits;
 use crate::page::Page;
+use crate::preset;
 use crate::pushover::Pushover;
+use crate::request::RequestDefaults;
+use crate::secret;
+use crate::snapshot::Snapshots;
+use crate::vars;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct Config {
+    #[serde(deserialize_with = "secret::deserialize_exposed")]
     pub database: PathBuf,
     pub page: Vec<Page>,
     pub pushover: Option<Pushover>,
+
+    /// Defaults for how pages are requested.
+    #[serde(default)]
+    pub request: RequestDefaults,
+
+    #[serde(default)]
+    pub limits: Limits,
+
+    #[serde(default)]
+    pub retention: Retention,
+
+    pub health: Option<Health>,
+    pub snapshots: Option<Snapshots>,
+
+    /// Glob patterns of further files with `[[page]]` entries, relative
+    /// to the directory of the configuration file.
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// The configuration file and the included files, in the order in
+    /// which they were read.
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
+}
+
+/// A file included in the configuration.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Include {
+    #[serde(default)]
+    page: Vec<Page>,
 }
 
 impl Config {
@@ -19,14 +60,397 @@ impl Config {
     ///
     /// This function returns an error if:
     ///
-    /// - the configuration file doesn't exist or
-    /// - the configuration file contains a parse error.
+    /// - the configuration file doesn't exist,
+    /// - the configuration file or an included file contains a parse
+    ///   error,
+    /// - an environment variable or a file that a value refers to
+    ///   doesn't exist,
+    /// - an `include` pattern is invalid,
+    /// - the `[request]` section sets an option that only pages can
+    ///   set,
+    /// - two pages have the same name, or
+    /// - a page sets both `body` and `form` or an invalid header.
     pub fn load(path: &str) -> Result<Self> {
-        let config: Config = toml::from_str(
-            &std::fs::read_to_string(path)
-                .with_context(|| path.to_string())?,
-        )?;
+        let path = Path::new(path);
+        let mut value = read(path)?;
+        let templates = match value
+            .as_table_mut()
+            .and_then(|x| x.remove("template"))
+        {
+            Some(toml::Value::Table(x)) => x,
+            Some(_) => bail!("{path:?}: template: expected a table"),
+            None => Default::default(),
+        };
+
+        if let Some(request) =
+            value.get("request").and_then(toml::Value::as_table)
+            && let Some(key) = RequestDefaults::PAGE_ONLY
+                .iter()
+                .find(|x| request.contains_key(**x))
+        {
+            bail!("{path:?}: request: {key} can only be set per page");
+        }
+
+        expand_pages(&mut value, &templates)
+            .with_context(|| format!("{path:?}"))?;
+
+        let mut config: Config =
+            value.try_into().with_context(|| format!("{path:?}"))?;
+        let mut names = HashMap::new();
+
+        for page in &config.page {
+            if names
+                .insert(page.name.clone(), path.to_path_buf())
+                .is_some()
+            {
+                bail!("page {:?} is defined more than once", page.name);
+            }
+        }
+
+        config.sources.push(
+            std::path::absolute(path)
+                .with_context(|| format!("{path:?}"))?,
+        );
+
+        // Pages from included files are appended to those of the
+        // configuration file.  Included files cannot change anything
+        // else.
+        //
+        for pattern in config.include_patterns() {
+            let mut n_files = 0;
+
+            for included_path in glob::glob(&pattern.to_string_lossy())
+                .with_context(|| format!("include {pattern:?}"))?
+            {
+                let included_path = included_path?;
+
+                n_files += 1;
+
+                if config.sources.contains(&included_path) {
+                    continue;
+                }
+
+                let mut value = read(&included_path)?;
+
+                expand_pages(&mut value, &templates)
+                    .with_context(|| format!("{included_path:?}"))?;
+
+                let included: Include = value
+                    .try_into()
+                    .with_context(|| format!("{included_path:?}"))?;
+
+                for page in included.page {
+                    if let Some(x) = names.insert(
+                        page.name.clone(),
+                        included_path.clone(),
+                    ) {
+                        bail!(
+                            "page {:?} in {:?} is already defined in {:?}",
+                            page.name,
+                            included_path,
+                            x
+                        );
+                    }
+
+                    config.page.push(page);
+                }
+
+                config.sources.push(included_path);
+            }
+
+            if n_files == 0 {
+                log::warn!("include {pattern:?} matches no files");
+            }
+        }
+
+        for page in &mut config.page {
+            page.request =
+                std::mem::take(&mut page.request).or(&config.request);
+            page.request
+                .validate()
+                .with_context(|| format!("page {:?}", page.name))?;
+        }
 
         Ok(config)
     }
+
+    /// Returns whether `path` is the configuration file or a file that
+    /// an `include` pattern matches, i.e., whether a change to `path`
+    /// can change the configuration.
+    pub fn is_source(&self, path: &Path) -> bool {
+        self.sources.first().is_some_and(|x| x == path)
+            || self.include_patterns().iter().any(|x| {
+                glob::Pattern::new(&x.to_string_lossy())
+                    .is_ok_and(|x| x.matches_path(path))
+            })
+    }
+
+    /// Returns the directory of the configuration file, followed by the
+    /// directories below which `include` patterns match files, and
+    /// whether files in their subdirectories can match too.
+    pub fn directories(&self) -> Vec<(PathBuf, bool)> {
+        let mut directories: Vec<(PathBuf, bool)> = self
+            .sources
+            .first()
+            .and_then(|x| x.parent())
+            .map(|x| (x.to_path_buf(), false))
+            .into_iter()
+            .collect();
+
+        for pattern in self.include_patterns() {
+            let directory: PathBuf = pattern
+                .parent()
+                .into_iter()
+                .flat_map(Path::components)
+                .take_while(|x| {
+                    !x.as_os_str()
+                        .to_string_lossy()
+                        .contains(['*', '?', '['])
+                })
+                .collect();
+            // A pattern such as `pages.d/*.toml` only matches files in
+            // `pages.d`, whereas `**/*.toml` and `*/pages.toml` match
+            // files in subdirectories too.
+            //
+            let recursive = pattern.components().count()
+                > directory.components().count() + 1;
+
+            match directories.iter_mut().find(|(x, _)| *x == directory)
+            {
+                Some((_, x)) => *x |= recursive,
+                None => directories.push((directory, recursive)),
+            }
+        }
+
+        directories
+    }
+
+    /// Returns the `include` patterns relative to the directory of the
+    /// configuration file.
+    fn include_patterns(&self) -> Vec<PathBuf> {
+        let directory = self
+            .sources
+            .first()
+            .and_then(|x| x.parent())
+            .unwrap_or(Path::new(""));
+
+        self.include.iter().map(|x| directory.join(x)).collect()
+    }
+}
+
+/// Reads a TOML file.
+fn read(path: &Path) -> Result<toml::Value> {
+    toml::from_str(
+        &std::fs::read_to_string(path)
+            .with_context(|| format!("{path:?}"))?,
+    )
+    .with_context(|| format!("{path:?}"))
+}
+
+/// Expands the templates, presets and variables of the pages in
+/// `value`.
+fn expand_pages(
+    value: &mut toml::Value,
+    templates: &toml::Table,
+) -> Result<()> {
+    if let Some(toml::Value::Array(pages)) = value.get_mut("page") {
+        let mut expanded = Vec::new();
+
+        for mut page in std::mem::take(pages) {
+            let name = page
+                .get("name")
+                .map_or("?".to_string(), ToString::to_string);
+
+            preset::expand(&mut page, templates)
+                .with_context(|| format!("page {name}"))?;
+            expanded.extend(
+                vars::expand(page)
+                    .with_context(|| format!("page {name}"))?,
+            );
+        }
+
+        *pages = expanded;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_includes_pages() {
+        let directory = std::env::temp_dir()
+            .join(format!("kairos-config-{}", std::process::id()));
+        let page = |name: &str| {
+            format!(
+                "[[page]]\nname = {name:?}\n\
+                 url = \"https://example.com/\"\nextract = \"a\"\n"
+            )
+        };
+
+        std::fs::create_dir_all(directory.join("pages.d")).unwrap();
+        std::fs::write(
+            directory.join("config.toml"),
+            format!(
+                "database = \"jobs.db\"\n\
+                 include = [\"pages.d/*.toml\"]\n{}",
+                page("a")
+            ),
+        )
+        .unwrap();
+        std::fs::write(directory.join("pages.d/2.toml"), page("c"))
+            .unwrap();
+        std::fs::write(directory.join("pages.d/1.toml"), page("b"))
+            .unwrap();
+
+        let path = directory.join("config.toml");
+        let config = Config::load(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(
+            config
+                .page
+                .iter()
+                .map(|x| x.name.as_str())
+                .collect::<Vec<_>>(),
+            ["a", "b", "c"]
+        );
+        assert_eq!(config.sources.len(), 3);
+        assert!(config.is_source(&config.sources[0]));
+        assert!(config.is_source(&directory.join("pages.d/4.toml")));
+        assert!(!config.is_source(&directory.join("jobs.db")));
+        assert_eq!(
+            config.directories(),
+            [
+                (directory.clone(), false),
+                (directory.join("pages.d"), false)
+            ]
+        );
+
+        std::fs::write(
+            directory.join("config.toml"),
+            format!(
+                "database = \"jobs.db\"\n\
+                 include = [\"pages.d/*.toml\", \"**/*.toml\"]\n{}",
+                page("a")
+            ),
+        )
+        .unwrap();
+
+        let config = Config::load(path.to_str().unwrap()).unwrap();
+
+        assert_eq!(config.page.len(), 3);
+        assert_eq!(
+            config.directories(),
+            [
+                (directory.clone(), true),
+                (directory.join("pages.d"), false)
+            ]
+        );
+
+        std::fs::write(directory.join("pages.d/3.toml"), page("a"))
+            .unwrap();
+
+        assert!(Config::load(path.to_str().unwrap()).is_err());
+
+        std::fs::write(
+            directory.join("pages.d/3.toml"),
+            "database = \"other.db\"\n",
+        )
+        .unwrap();
+
+        assert!(Config::load(path.to_str().unwrap()).is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn load_rejects_invalid_request_options() {
+        let path = std::env::temp_dir().join(format!(
+            "kairos-request-{}.toml",
+            std::process::id()
+        ));
+        let load = |toml: &str| {
+            std::fs::write(
+                &path,
+                format!(
+                    "database = \"jobs.db\"\n{toml}\n\
+                     [[page]]\nname = \"a\"\n\
+                     url = \"https://example.com/\"\nextract = \"a\"\n\
+                     form = {{ q = \"engineer\" }}\n"
+                ),
+            )
+            .unwrap();
+
+            Config::load(path.to_str().unwrap())
+        };
+
+        let config =
+            load("[request]\nheaders = { Accept = \"*/*\" }").unwrap();
+
+        assert_eq!(
+            config.page[0].request.headers["Accept"].expose(),
+            "*/*"
+        );
+        assert!(config.page[0].request.body.is_none());
+
+        assert!(load("[request]\nbody = \"q=engineer\"").is_err());
+        assert!(
+            load("[request]\nheaders = { \"A B\" = \"c\" }").is_err()
+        );
+        assert!(load("[request]\nheaders = { A = \"b\\nc\" }").is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn load_resolves_secrets_without_exposing_them() {
+        let directory = std::env::temp_dir()
+            .join(format!("kairos-secrets-{}", std::process::id()));
+
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("token"), "hunter2\n").unwrap();
+        std::fs::write(
+            directory.join("url"),
+            "https://example.com/jobs?key=hunter3\n",
+        )
+        .unwrap();
+        std::fs::write(directory.join("database"), "/var/lib/jobs.db")
+            .unwrap();
+        std::fs::write(
+            directory.join("config.toml"),
+            format!(
+                "database = {{ file = {:?} }}\n\
+                 [[page]]\nname = \"a\"\n\
+                 url = {{ file = {:?} }}\nextract = \"a\"\n\
+                 headers = {{ Authorization = {{ file = {:?} }} }}\n\
+                 form = {{ file = \"cv\" }}\n",
+                directory.join("database"),
+                directory.join("url"),
+                directory.join("token")
+            ),
+        )
+        .unwrap();
+
+        let config = Config::load(
+            directory.join("config.toml").to_str().unwrap(),
+        )
+        .unwrap();
+        let page = &config.page[0];
+
+        assert_eq!(
+            page.request.headers["Authorization"].expose(),
+            "hunter2"
+        );
+        assert_eq!(
+            page.request.form.as_ref().unwrap()["file"].expose(),
+            "cv"
+        );
+        assert!(!format!("{page:?}").contains("hunter2"));
+        assert_eq!(page.url, "https://example.com/jobs?key=hunter3");
+        assert_eq!(config.database, Path::new("/var/lib/jobs.db"));
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
 }
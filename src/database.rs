@@ -1,35 +1,290 @@
-use anyhow::{Context, Result};
-use rusqlite::Connection;
+use anyhow::{bail, Context, Result};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::page::Extract;
+use crate::page::{Extract, Link};
 
+/// A connection pool with one connection for writing and several
+/// read-only connections.  Because the database is in WAL mode, reads
+/// through the read-only connections don't wait for writes.
 #[derive(Debug, Clone)]
 pub struct Database {
-    connection: Arc<Mutex<Connection>>,
+    writer: Arc<Mutex<Connection>>,
+    readers: Vec<Arc<Mutex<Connection>>>,
+    next_reader: Arc<AtomicUsize>,
 }
 
+/// A link along with the page it was found on and the times it was
+/// first and last observed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LinkRecord {
+    pub page: Option<String>,
+    pub url: String,
+    pub extract: String,
+    pub href: String,
+    pub text: String,
+    pub first_seen: String,
+    pub last_seen: String,
+    pub is_active: bool,
+}
+
+/// The outcome of requesting a page in a collection.  Fields are
+/// `None` if the run failed before they could be determined.
+#[derive(Debug, Default, Clone)]
+pub struct PageRun {
+    pub http_status: Option<u16>,
+    pub duration_ms: u64,
+    pub n_bytes: Option<u64>,
+    pub n_links: Option<u64>,
+    pub n_new_links: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// The `ETag` and `Last-Modified` headers of the last successful
+/// response for a page, which make the next request conditional.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// How long to keep collection history.  Without any settings,
+/// history is kept forever.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct Retention {
+    /// Delete observations from collections older than this many days,
+    /// except the first and last observation of each link, and page
+    /// runs from those collections.
+    pub keep_collections_days: Option<u64>,
+
+    /// Delete inactive links that haven't been observed for this many
+    /// days, along with their observations.
+    pub keep_inactive_links_days: Option<u64>,
+
+    /// Only keep the first and last observation of each link.
+    #[serde(default)]
+    pub keep_first_and_last: bool,
+}
+
+impl Retention {
+    pub fn is_enabled(&self) -> bool {
+        self.keep_collections_days.is_some()
+            || self.keep_inactive_links_days.is_some()
+            || self.keep_first_and_last
+    }
+}
+
+/// Counts of what pruning deleted from the database.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PruneStats {
+    pub n_links: u64,
+    pub n_observations: u64,
+    pub n_collections: u64,
+}
+
+/// Counts of what an import added to the database.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ImportStats {
+    pub n_links: u64,
+    pub n_new_links: u64,
+    pub n_observations: u64,
+}
+
+/// A step in the evolution of the database schema.  Migrations are
+/// applied in order, and `PRAGMA user_version` records the version of
+/// the last one applied.
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    sql: &'static str,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "page_runs",
+        sql: include_str!("migrations/0002_page_runs.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "cache_validators",
+        sql: include_str!("migrations/0003_cache_validators.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "content_hash",
+        sql: include_str!("migrations/0004_content_hash.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "snapshots",
+        sql: include_str!("migrations/0005_snapshots.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "baselined",
+        sql: include_str!("migrations/0006_baselined.sql"),
+    },
+];
+
 impl Database {
-    const SCHEMA: &str = include_str!("schema.sql");
+    const PRAGMAS: &str = include_str!("pragmas.sql");
+    const N_READERS: usize = 4;
 
+    /// Opens the database and applies any pending migrations.
     pub fn try_new(path: impl AsRef<Path>) -> Result<Self> {
+        let database = Self::open(path)?;
+
+        for migration in database.migrate()? {
+            log::info!(
+                "database: applied migration {:04} {}",
+                migration.version,
+                migration.name
+            );
+        }
+
+        Ok(database)
+    }
+
+    /// Opens the database without applying migrations.
+    ///
+    /// # Errors
+    ///
+    /// This function returns an error if the database cannot be opened
+    /// or if its schema is newer than this version of Kairos supports.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
         let connection = tokio::task::block_in_place(move || {
             Connection::open(path)
         })?;
 
         connection
-            .execute_batch(Self::SCHEMA)
-            .context("database schema")?;
+            .execute_batch(Self::PRAGMAS)
+            .context("database pragmas")?;
+
+        let version = schema_version(&connection)?;
+        let latest = MIGRATIONS.last().map_or(0, |x| x.version);
+
+        if version > latest {
+            bail!(
+                "database schema version {version} is newer than \
+                 the latest supported version {latest}"
+            );
+        }
+
+        // An in-memory database is private to its connection, so reads
+        // have to go through the writer.
+        //
+        let readers = if path == Path::new(":memory:") {
+            Vec::new()
+        } else {
+            (0..Self::N_READERS)
+                .map(|_| {
+                    let reader = tokio::task::block_in_place(|| {
+                        Connection::open_with_flags(
+                            path,
+                            OpenFlags::SQLITE_OPEN_READ_ONLY
+                                | OpenFlags::SQLITE_OPEN_NO_MUTEX
+                                | OpenFlags::SQLITE_OPEN_URI,
+                        )
+                    })
+                    .context("database reader")?;
+
+                    Ok(Arc::new(Mutex::new(reader)))
+                })
+                .collect::<Result<_>>()?
+        };
 
         Ok(Self {
-            connection: Arc::new(Mutex::new(connection)),
+            writer: Arc::new(Mutex::new(connection)),
+            readers,
+            next_reader: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// Returns a read-only connection, preferring one that is not in
+    /// use.
+    fn reader(&self) -> Arc<Mutex<Connection>> {
+        if self.readers.is_empty() {
+            return self.writer.clone();
+        }
+
+        let start = self.next_reader.fetch_add(1, Ordering::Relaxed);
+
+        (0..self.readers.len())
+            .map(|i| &self.readers[(start + i) % self.readers.len()])
+            .find(|reader| reader.try_lock().is_ok())
+            .unwrap_or(&self.readers[start % self.readers.len()])
+            .clone()
+    }
+
+    /// Returns the migrations that have not been applied yet.
+    pub fn pending_migrations(
+        &self,
+    ) -> Result<Vec<&'static Migration>> {
+        tokio::task::block_in_place(|| {
+            let version = schema_version(&self.writer.blocking_lock())?;
+
+            Ok(MIGRATIONS
+                .iter()
+                .filter(|x| x.version > version)
+                .collect())
         })
     }
 
+    /// Applies the pending migrations in order, each in its own
+    /// transaction, and returns them.
+    pub fn migrate(&self) -> Result<Vec<&'static Migration>> {
+        let pending = self.pending_migrations()?;
+
+        tokio::task::block_in_place(|| -> Result<()> {
+            let mut connection = self.writer.blocking_lock();
+
+            for migration in &pending {
+                let tx =
+                    connection.transaction().with_context(|| {
+                        format!(
+                            "database migration {}",
+                            migration.version
+                        )
+                    })?;
+
+                tx.execute_batch(migration.sql)
+                    .and_then(|()| {
+                        tx.pragma_update(
+                            None,
+                            "user_version",
+                            migration.version,
+                        )
+                    })
+                    .and_then(|()| tx.commit())
+                    .with_context(|| {
+                        format!(
+                            "database migration {}",
+                            migration.version
+                        )
+                    })?;
+            }
+
+            Ok(())
+        })?;
+
+        Ok(pending)
+    }
+
     pub async fn start_collection(&self) -> Result<i64> {
-        let connection = self.connection.clone();
+        let connection = self.writer.clone();
 
         tokio::task::spawn_blocking(move || {
             #[rustfmt::skip]
@@ -53,8 +308,9 @@ impl Database {
         n_pages: u64,
         n_links: u64,
         n_new_links: u64,
+        n_failed_pages: u64,
     ) -> Result<()> {
-        let connection = self.connection.clone();
+        let connection = self.writer.clone();
 
         tokio::task::spawn_blocking(move || {
             #[rustfmt::skip]
@@ -65,9 +321,16 @@ impl Database {
                      SET end_time = DATETIME('now', 'utc'), \
                      n_pages = ?1, \
                      n_links = ?2, \
-                     n_new_links = ?3 \
-                     WHERE id = ?4",
-                    (n_pages, n_links, n_new_links, collection_id),
+                     n_new_links = ?3, \
+                     n_failed_pages = ?4 \
+                     WHERE id = ?5",
+                    (
+                        n_pages,
+                        n_links,
+                        n_new_links,
+                        n_failed_pages,
+                        collection_id,
+                    ),
                 )
                 .context("database.end_collection: INSERT")?;
 
@@ -81,7 +344,7 @@ impl Database {
         url: &str,
         extract: &Extract,
     ) -> Result<i64> {
-        let connection = self.connection.clone();
+        let connection = self.writer.clone();
         let url = url.to_string();
         let extract_str = extract.to_string();
 
@@ -112,182 +375,1769 @@ impl Database {
         .await?
     }
 
-    pub async fn add_link(
+    /// Records the links observed on the page in the collection in a
+    /// single transaction.  Links that are not in the database yet are
+    /// added, every link is attached to the collection, and links on
+    /// the page that were not observed are marked as inactive.
+    ///
+    /// Returns each link along with whether it was new.
+    pub async fn record_links(
         &self,
         page_id: i64,
-        href: &str,
-        text: &str,
-    ) -> Result<i64> {
-        let connection = self.connection.clone();
-        let href = href.to_string();
-        let text = text.to_string();
+        collection_id: i64,
+        links: Vec<Link>,
+    ) -> Result<Vec<(Link, bool)>> {
+        let connection = self.writer.clone();
 
         tokio::task::spawn_blocking(move || {
+            let mut connection = connection.blocking_lock();
+            let tx = connection
+                .transaction()
+                .context("database.record_links: BEGIN")?;
+            let mut result = Vec::with_capacity(links.len());
+
+            for link in links {
+                let (link_id, is_new) = upsert_link(
+                    &tx, page_id, &link.href, &link.text, true,
+                )?;
+
+                #[rustfmt::skip]
+                tx.execute(
+                    "INSERT INTO links_collections \
+                     (link_id, collection_id, timestamp) \
+                     VALUES (?1, ?2, DATETIME('now', 'utc'))",
+                    (link_id, collection_id),
+                )
+                .context("database.record_links: INSERT")?;
+
+                result.push((link, is_new));
+            }
+
             #[rustfmt::skip]
-            connection
-            .blocking_lock()
-            .execute(
-                "INSERT OR IGNORE INTO links (page_id, href, text) \
-                 VALUES (?1, ?2, ?3)",
-                (page_id, &href, &text),
+            tx.execute(
+                "UPDATE links SET is_active = EXISTS ( \
+                 SELECT 1 FROM links_collections \
+                 WHERE link_id = links.id AND collection_id = ?2) \
+                 WHERE page_id = ?1",
+                (page_id, collection_id),
             )
-            .context("database.add_link: INSERT OR IGNORE")?;
+            .context("database.record_links: UPDATE")?;
+
+            tx.commit().context("database.record_links: COMMIT")?;
+
+            Ok(result)
+        })
+        .await?
+    }
+
+    pub async fn add_page_run(
+        &self,
+        collection_id: i64,
+        page_id: i64,
+        run: PageRun,
+    ) -> Result<()> {
+        let connection = self.writer.clone();
 
+        tokio::task::spawn_blocking(move || {
             #[rustfmt::skip]
-            let link_id = connection
+            connection
                 .blocking_lock()
-                .query_row(
-                    "SELECT id FROM links \
-                     WHERE page_id = ?1 AND href = ?2 AND text = ?3",
-                    (page_id, &href, &text),
-                    |row| row.get(0),
+                .execute(
+                    "INSERT INTO page_runs \
+                     (collection_id, page_id, timestamp, http_status, \
+                     duration_ms, n_bytes, n_links, n_new_links, error) \
+                     VALUES (?1, ?2, DATETIME('now', 'utc'), \
+                     ?3, ?4, ?5, ?6, ?7, ?8)",
+                    (
+                        collection_id,
+                        page_id,
+                        run.http_status,
+                        run.duration_ms,
+                        run.n_bytes,
+                        run.n_links,
+                        run.n_new_links,
+                        &run.error,
+                    ),
                 )
-                .context("database.add_link: SELECT")?;
+                .context("database.add_page_run: INSERT")?;
 
-            Ok(link_id)
+            Ok(())
         })
         .await?
     }
 
-    pub async fn link_exists(
+    /// Returns the most recent runs of the page with the given URL and
+    /// extract, newest first.
+    pub async fn recent_page_runs(
         &self,
-        page_id: i64,
-        href: &str,
-        text: &str,
-    ) -> Result<bool> {
-        let connection = self.connection.clone();
-        let href = href.to_string();
-        let text = text.to_string();
+        url: &str,
+        extract: &Extract,
+        limit: usize,
+    ) -> Result<Vec<PageRun>> {
+        let connection = self.reader();
+        let url = url.to_string();
+        let extract_str = extract.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.blocking_lock();
+
+            #[rustfmt::skip]
+            let mut statement = connection
+                .prepare(
+                    "SELECT page_runs.http_status, \
+                     page_runs.duration_ms, page_runs.n_bytes, \
+                     page_runs.n_links, page_runs.n_new_links, \
+                     page_runs.error \
+                     FROM page_runs \
+                     JOIN pages ON pages.id = page_runs.page_id \
+                     WHERE pages.url = ?1 AND pages.extract = ?2 \
+                     ORDER BY page_runs.id DESC \
+                     LIMIT ?3",
+                )
+                .context("database.recent_page_runs: SELECT")?;
+
+            let runs = statement
+                .query_map((&url, &extract_str, limit), |row| {
+                    Ok(PageRun {
+                        http_status: row.get(0)?,
+                        duration_ms: row.get(1)?,
+                        n_bytes: row.get(2)?,
+                        n_links: row.get(3)?,
+                        n_new_links: row.get(4)?,
+                        error: row.get(5)?,
+                    })
+                })
+                .context("database.recent_page_runs: SELECT")?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .context("database.recent_page_runs: SELECT")?;
+
+            Ok(runs)
+        })
+        .await?
+    }
+
+    /// Returns whether a previous collection of the page succeeded,
+    /// even if it found no links, or whether the page has links, e.g.,
+    /// imported ones.
+    pub async fn page_has_history(&self, page_id: i64) -> Result<bool> {
+        let connection = self.reader();
 
         tokio::task::spawn_blocking(move || {
             #[rustfmt::skip]
-            let count: i64 = connection
+            let has_history: Option<bool> = connection
                 .blocking_lock()
                 .query_row(
-                    "SELECT COUNT(*) FROM links \
-                     WHERE page_id = ?1 AND href = ?2 AND text = ?3",
-                    (page_id, &href, &text),
+                    "SELECT baselined OR EXISTS \
+                     (SELECT 1 FROM links WHERE links.page_id = pages.id) \
+                     FROM pages WHERE id = ?1",
+                    (page_id,),
                     |row| row.get(0),
                 )
-                .context("database.link_exists: SELECT")?;
+                .optional()
+                .context("database.page_has_history: SELECT")?;
+
+            Ok(has_history.unwrap_or(false))
+        })
+        .await?
+    }
+
+    /// Records that a collection of the page succeeded, so that later
+    /// collections aren't treated as its first.
+    pub async fn set_baselined(&self, page_id: i64) -> Result<()> {
+        let connection = self.writer.clone();
+
+        tokio::task::spawn_blocking(move || {
+            connection
+                .blocking_lock()
+                .execute(
+                    "UPDATE pages SET baselined = 1 WHERE id = ?1",
+                    (page_id,),
+                )
+                .context("database.set_baselined: UPDATE")?;
 
-            Ok(count > 0)
+            Ok(())
         })
         .await?
     }
 
-    pub async fn add_link_collection(
+    pub async fn cache_validators(
         &self,
-        link_id: i64,
-        collection_id: i64,
+        page_id: i64,
+    ) -> Result<CacheValidators> {
+        let connection = self.reader();
+
+        tokio::task::spawn_blocking(move || {
+            connection
+                .blocking_lock()
+                .query_row(
+                    "SELECT etag, last_modified FROM pages WHERE id = ?1",
+                    (page_id,),
+                    |row| {
+                        Ok(CacheValidators {
+                            etag: row.get(0)?,
+                            last_modified: row.get(1)?,
+                        })
+                    },
+                )
+                .optional()
+                .context("database.cache_validators: SELECT")
+                .map(Option::unwrap_or_default)
+        })
+        .await?
+    }
+
+    pub async fn set_cache_validators(
+        &self,
+        page_id: i64,
+        validators: CacheValidators,
     ) -> Result<()> {
-        let connection = self.connection.clone();
+        let connection = self.writer.clone();
 
         tokio::task::spawn_blocking(move || {
             #[rustfmt::skip]
             connection
-            .blocking_lock()
-            .execute(
-                "INSERT INTO links_collections \
-                 (link_id, collection_id, timestamp) \
-                 VALUES (?1, ?2, DATETIME('now', 'utc'))",
-                (link_id, collection_id),
-            )
-            .context("database.add_link_collection: INSERT")?;
+                .blocking_lock()
+                .execute(
+                    "UPDATE pages SET etag = ?2, last_modified = ?3 \
+                     WHERE id = ?1",
+                    (
+                        page_id,
+                        &validators.etag,
+                        &validators.last_modified,
+                    ),
+                )
+                .context("database.set_cache_validators: UPDATE")?;
 
             Ok(())
         })
         .await?
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use scraper::selector::Selector;
+    pub async fn content_hash(
+        &self,
+        page_id: i64,
+    ) -> Result<Option<String>> {
+        let connection = self.reader();
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn add_page_does_not_add_duplicate() {
-        let db = Database::try_new(":memory:").unwrap();
-        let sel = Selector::parse("a").unwrap();
-        let ex = Extract::CSSPlain(sel);
+        tokio::task::spawn_blocking(move || {
+            connection
+                .blocking_lock()
+                .query_row(
+                    "SELECT content_hash FROM pages WHERE id = ?1",
+                    (page_id,),
+                    |row| row.get(0),
+                )
+                .optional()
+                .context("database.content_hash: SELECT")
+                .map(Option::flatten)
+        })
+        .await?
+    }
 
-        let id_a = db.add_page("http://foo.bar", &ex).await.unwrap();
-        let id_b = db.add_page("http://foo.bar", &ex).await.unwrap();
+    pub async fn set_content_hash(
+        &self,
+        page_id: i64,
+        content_hash: String,
+    ) -> Result<()> {
+        let connection = self.writer.clone();
 
-        assert_eq!(id_a, id_b);
+        tokio::task::spawn_blocking(move || {
+            connection
+                .blocking_lock()
+                .execute(
+                    "UPDATE pages SET content_hash = ?2 WHERE id = ?1",
+                    (page_id, &content_hash),
+                )
+                .context("database.set_content_hash: UPDATE")?;
+
+            Ok(())
+        })
+        .await?
     }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn add_page_accounts_for_url() {
-        let db = Database::try_new(":memory:").unwrap();
-        let sel = Selector::parse("a").unwrap();
-        let ex = Extract::CSSPlain(sel);
+    /// Records the links on the page that were observed in the page's
+    /// most recent collection as observed again in this collection, for
+    /// a page that hasn't changed.
+    ///
+    /// Returns the number of links.
+    pub async fn reobserve_links(
+        &self,
+        page_id: i64,
+        collection_id: i64,
+    ) -> Result<u64> {
+        let connection = self.writer.clone();
 
-        let id_a = db.add_page("http://foo/bar", &ex).await.unwrap();
-        let id_b = db.add_page("http://foo/baz", &ex).await.unwrap();
+        tokio::task::spawn_blocking(move || {
+            #[rustfmt::skip]
+            let n_links = connection
+                .blocking_lock()
+                .execute(
+                    "INSERT INTO links_collections \
+                     (link_id, collection_id, timestamp) \
+                     SELECT id, ?2, DATETIME('now', 'utc') FROM links \
+                     WHERE page_id = ?1 AND is_active",
+                    (page_id, collection_id),
+                )
+                .context("database.reobserve_links: INSERT")?;
 
-        assert_ne!(id_a, id_b);
+            Ok(n_links as u64)
+        })
+        .await?
     }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn add_page_accounts_for_extract() {
-        let db = Database::try_new(":memory:").unwrap();
-        let sel_a = Selector::parse("a[href^='/foo']").unwrap();
-        let sel_b = Selector::parse("a[href^='/bar']").unwrap();
-        let ex_a = Extract::CSSPlain(sel_a);
-        let ex_b = Extract::CSSPlain(sel_b);
+    /// Returns every observed link, optionally restricted to links
+    /// first seen at or after `since`.  The `page` field of the records
+    /// is left empty because page names are not stored in the
+    /// database.
+    pub async fn link_records(
+        &self,
+        since: Option<&str>,
+    ) -> Result<Vec<LinkRecord>> {
+        let connection = self.reader();
+        let since = since.map(str::to_string);
 
-        let id_a = db.add_page("http://foo.bar", &ex_a).await.unwrap();
-        let id_b = db.add_page("http://foo.bar", &ex_b).await.unwrap();
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.blocking_lock();
 
-        assert_ne!(id_a, id_b);
-    }
+            if let Some(x) = &since {
+                let is_valid: bool = connection
+                    .query_row(
+                        "SELECT DATETIME(?1) IS NOT NULL",
+                        (x,),
+                        |row| row.get(0),
+                    )
+                    .context("database.link_records: SELECT")?;
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn add_link_requires_valid_page_id() {
-        let db = Database::try_new(":memory:").unwrap();
-        let nonexistent = 1;
+                if !is_valid {
+                    bail!("invalid date or time: {x:?}");
+                }
+            }
+
+            #[rustfmt::skip]
+            let mut statement = connection
+                .prepare(
+                    "SELECT pages.url, pages.extract, \
+                     links.href, links.text, \
+                     MIN(links_collections.timestamp), \
+                     MAX(links_collections.timestamp), \
+                     links.is_active \
+                     FROM links \
+                     JOIN pages ON pages.id = links.page_id \
+                     JOIN links_collections \
+                     ON links_collections.link_id = links.id \
+                     GROUP BY links.id \
+                     HAVING ?1 IS NULL \
+                     OR MIN(links_collections.timestamp) \
+                     >= DATETIME(?1) \
+                     ORDER BY MIN(links_collections.timestamp), \
+                     links.id",
+                )
+                .context("database.link_records: SELECT")?;
+
+            let records = statement
+                .query_map((&since,), |row| {
+                    Ok(LinkRecord {
+                        page: None,
+                        url: row.get(0)?,
+                        extract: row.get(1)?,
+                        href: row.get(2)?,
+                        text: row.get(3)?,
+                        first_seen: row.get(4)?,
+                        last_seen: row.get(5)?,
+                        is_active: row.get(6)?,
+                    })
+                })
+                .context("database.link_records: SELECT")?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .context("database.link_records: SELECT")?;
 
-        assert!(db.add_link(nonexistent, "/foo", "bar").await.is_err());
+            Ok(records)
+        })
+        .await?
     }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn add_link_does_not_add_duplicate() {
-        let db = Database::try_new(":memory:").unwrap();
-        let sel = Selector::parse("a").unwrap();
-        let ex = Extract::CSSPlain(sel);
-        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+    /// Deletes collection history according to the retention settings
+    /// in a single transaction.
+    pub async fn prune(
+        &self,
+        retention: &Retention,
+    ) -> Result<PruneStats> {
+        let connection = self.writer.clone();
+        let retention = retention.clone();
 
-        let id_a = db.add_link(page_id, "/foo", "bar").await.unwrap();
-        let id_b = db.add_link(page_id, "/foo", "bar").await.unwrap();
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.blocking_lock();
+            let tx =
+                connection.transaction().context("database.prune: BEGIN")?;
+            let mut stats = PruneStats::default();
 
-        assert_eq!(id_a, id_b);
+            // An observation is the first or last of its link if no
+            // other observation of the link precedes or follows it.
+            //
+            #[rustfmt::skip]
+            const IS_FIRST_OR_LAST: &str =
+                "(NOT EXISTS ( \
+                 SELECT 1 FROM links_collections AS other \
+                 WHERE other.link_id = links_collections.link_id \
+                 AND (other.timestamp, other.rowid) \
+                 < (links_collections.timestamp, links_collections.rowid)) \
+                 OR NOT EXISTS ( \
+                 SELECT 1 FROM links_collections AS other \
+                 WHERE other.link_id = links_collections.link_id \
+                 AND (other.timestamp, other.rowid) \
+                 > (links_collections.timestamp, links_collections.rowid)))";
+
+            if let Some(days) = retention.keep_inactive_links_days {
+                let cutoff = format!("-{days} days");
+
+                #[rustfmt::skip]
+                tx.execute(
+                    "CREATE TEMP TABLE pruned_links AS \
+                     SELECT links.id FROM links \
+                     WHERE NOT links.is_active \
+                     AND NOT EXISTS ( \
+                     SELECT 1 FROM links_collections \
+                     WHERE link_id = links.id \
+                     AND timestamp >= DATETIME('now', 'utc', ?1))",
+                    (&cutoff,),
+                )
+                .context("database.prune: CREATE TEMP TABLE")?;
+
+                #[rustfmt::skip]
+                let n_observations = tx
+                    .execute(
+                        "DELETE FROM links_collections \
+                         WHERE link_id IN (SELECT id FROM pruned_links)",
+                        (),
+                    )
+                    .context("database.prune: DELETE")?;
+
+                #[rustfmt::skip]
+                let n_links = tx
+                    .execute(
+                        "DELETE FROM links \
+                         WHERE id IN (SELECT id FROM pruned_links)",
+                        (),
+                    )
+                    .context("database.prune: DELETE")?;
+
+                tx.execute("DROP TABLE pruned_links", ())
+                    .context("database.prune: DROP TABLE")?;
+
+                stats.n_observations += n_observations as u64;
+                stats.n_links += n_links as u64;
+            }
+
+            if let Some(days) = retention.keep_collections_days {
+                let cutoff = format!("-{days} days");
+
+                #[rustfmt::skip]
+                let n_observations = tx
+                    .execute(
+                        &format!(
+                            "DELETE FROM links_collections \
+                             WHERE collection_id IN ( \
+                             SELECT id FROM collections \
+                             WHERE start_time \
+                             < DATETIME('now', 'utc', ?1)) \
+                             AND NOT {IS_FIRST_OR_LAST}"
+                        ),
+                        (&cutoff,),
+                    )
+                    .context("database.prune: DELETE")?;
+
+                #[rustfmt::skip]
+                tx.execute(
+                    "DELETE FROM page_runs \
+                     WHERE collection_id IN ( \
+                     SELECT id FROM collections \
+                     WHERE start_time < DATETIME('now', 'utc', ?1))",
+                    (&cutoff,),
+                )
+                .context("database.prune: DELETE")?;
+
+                #[rustfmt::skip]
+                tx.execute(
+                    "DELETE FROM snapshots \
+                     WHERE collection_id IN ( \
+                     SELECT id FROM collections \
+                     WHERE start_time < DATETIME('now', 'utc', ?1))",
+                    (&cutoff,),
+                )
+                .context("database.prune: DELETE")?;
+
+                #[rustfmt::skip]
+                let n_collections = tx
+                    .execute(
+                        "DELETE FROM collections \
+                         WHERE start_time < DATETIME('now', 'utc', ?1) \
+                         AND NOT EXISTS ( \
+                         SELECT 1 FROM links_collections \
+                         WHERE collection_id = collections.id)",
+                        (&cutoff,),
+                    )
+                    .context("database.prune: DELETE")?;
+
+                stats.n_observations += n_observations as u64;
+                stats.n_collections += n_collections as u64;
+            }
+
+            if retention.keep_first_and_last {
+                let n_observations = tx
+                    .execute(
+                        &format!(
+                            "DELETE FROM links_collections \
+                             WHERE NOT {IS_FIRST_OR_LAST}"
+                        ),
+                        (),
+                    )
+                    .context("database.prune: DELETE")?;
+
+                stats.n_observations += n_observations as u64;
+            }
+
+            tx.commit().context("database.prune: COMMIT")?;
+
+            Ok(stats)
+        })
+        .await?
     }
 
-    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
-    async fn link_exists_works() {
-        let db = Database::try_new(":memory:").unwrap();
-        let sel = Selector::parse("a").unwrap();
-        let ex = Extract::CSSPlain(sel);
-        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+    /// Stores the compressed body of a page fetched in the collection.
+    pub async fn add_snapshot(
+        &self,
+        collection_id: i64,
+        page_id: i64,
+        body: Vec<u8>,
+    ) -> Result<()> {
+        let connection = self.writer.clone();
+
+        tokio::task::spawn_blocking(move || {
+            #[rustfmt::skip]
+            connection
+                .blocking_lock()
+                .execute(
+                    "INSERT OR REPLACE INTO snapshots \
+                     (collection_id, page_id, timestamp, body) \
+                     VALUES (?1, ?2, DATETIME('now', 'utc'), ?3)",
+                    (collection_id, page_id, &body),
+                )
+                .context("database.add_snapshot: INSERT")?;
 
-        assert!(!db.link_exists(page_id, "/foo", "bar").await.unwrap());
-        assert!(!db.link_exists(page_id, "/bar", "baz").await.unwrap());
+            Ok(())
+        })
+        .await?
+    }
 
-        db.add_link(page_id, "/foo", "bar").await.unwrap();
+    /// Returns the compressed body of a page fetched in the collection,
+    /// if a snapshot of it was stored.
+    pub async fn snapshot(
+        &self,
+        collection_id: i64,
+        page_id: i64,
+    ) -> Result<Option<Vec<u8>>> {
+        let connection = self.reader();
 
-        assert!(db.link_exists(page_id, "/foo", "bar").await.unwrap());
-        assert!(!db.link_exists(page_id, "/bar", "baz").await.unwrap());
+        tokio::task::spawn_blocking(move || {
+            #[rustfmt::skip]
+            let body = connection
+                .blocking_lock()
+                .query_row(
+                    "SELECT body FROM snapshots \
+                     WHERE collection_id = ?1 AND page_id = ?2",
+                    (collection_id, page_id),
+                    |row| row.get(0),
+                )
+                .optional()
+                .context("database.snapshot: SELECT")?;
 
-        db.add_link(page_id, "/lorem", "ipsum").await.unwrap();
+            Ok(body)
+        })
+        .await?
+    }
 
-        assert!(db.link_exists(page_id, "/foo", "bar").await.unwrap());
-        assert!(!db.link_exists(page_id, "/bar", "baz").await.unwrap());
+    /// Deletes snapshots older than `days`.
+    pub async fn delete_snapshots(&self, days: u64) -> Result<u64> {
+        let connection = self.writer.clone();
 
-        db.add_link(page_id, "/bar", "baz").await.unwrap();
+        tokio::task::spawn_blocking(move || {
+            let n_snapshots = connection
+                .blocking_lock()
+                .execute(
+                    "DELETE FROM snapshots \
+                     WHERE timestamp < DATETIME('now', 'utc', ?1)",
+                    (format!("-{days} days"),),
+                )
+                .context("database.delete_snapshots: DELETE")?;
+
+            Ok(n_snapshots as u64)
+        })
+        .await?
+    }
+
+    /// Returns the ID and URL of each page requested in the collection.
+    pub async fn collection_pages(
+        &self,
+        collection_id: i64,
+    ) -> Result<Vec<(i64, String)>> {
+        let connection = self.reader();
+
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.blocking_lock();
+
+            #[rustfmt::skip]
+            let mut statement = connection
+                .prepare(
+                    "SELECT pages.id, pages.url FROM page_runs \
+                     JOIN pages ON pages.id = page_runs.page_id \
+                     WHERE page_runs.collection_id = ?1 \
+                     ORDER BY page_runs.id",
+                )
+                .context("database.collection_pages: SELECT")?;
+
+            let pages = statement
+                .query_map((collection_id,), |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .context("database.collection_pages: SELECT")?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .context("database.collection_pages: SELECT")?;
+
+            Ok(pages)
+        })
+        .await?
+    }
+
+    /// Returns the links on the page that were observed in the
+    /// collection.
+    pub async fn observed_links(
+        &self,
+        collection_id: i64,
+        page_id: i64,
+    ) -> Result<Vec<Link>> {
+        let connection = self.reader();
+
+        tokio::task::spawn_blocking(move || {
+            let connection = connection.blocking_lock();
+
+            #[rustfmt::skip]
+            let mut statement = connection
+                .prepare(
+                    "SELECT links.href, links.text FROM links_collections \
+                     JOIN links ON links.id = links_collections.link_id \
+                     WHERE links_collections.collection_id = ?1 \
+                     AND links.page_id = ?2 \
+                     ORDER BY links_collections.rowid",
+                )
+                .context("database.observed_links: SELECT")?;
+
+            let links = statement
+                .query_map((collection_id, page_id), |row| {
+                    Ok(Link {
+                        href: row.get(0)?,
+                        text: row.get(1)?,
+                    })
+                })
+                .context("database.observed_links: SELECT")?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .context("database.observed_links: SELECT")?;
+
+            Ok(links)
+        })
+        .await?
+    }
+
+    /// Returns free pages to the file system.  This only has an effect
+    /// if the database was created with `auto_vacuum = INCREMENTAL`.
+    pub async fn vacuum(&self) -> Result<()> {
+        let connection = self.writer.clone();
+
+        tokio::task::spawn_blocking(move || {
+            connection
+                .blocking_lock()
+                .execute_batch("PRAGMA incremental_vacuum")
+                .context(
+                    "database.vacuum: PRAGMA incremental_vacuum",
+                )?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Merges exported link records into the database in a single
+    /// transaction.
+    ///
+    /// Each record's first and last observation is attached to a new
+    /// collection that represents the import.  Observations that are
+    /// already in the database with the same timestamp are skipped,
+    /// so importing the same records twice does not add duplicates.
+    pub async fn import_records(
+        &self,
+        records: Vec<LinkRecord>,
+    ) -> Result<ImportStats> {
+        let connection = self.writer.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.blocking_lock();
+            let tx = connection
+                .transaction()
+                .context("database.import_records: BEGIN")?;
+            let mut stats = ImportStats::default();
+
+            #[rustfmt::skip]
+            tx.execute(
+                "INSERT INTO collections (start_time, end_time) \
+                 VALUES (DATETIME('now', 'utc'), DATETIME('now', 'utc'))",
+                (),
+            )
+            .context("database.import_records: INSERT")?;
+
+            let collection_id = tx.last_insert_rowid();
+
+            for record in &records {
+                let page_id =
+                    upsert_page(&tx, &record.url, &record.extract)?;
+                let (link_id, is_new) = upsert_link(
+                    &tx,
+                    page_id,
+                    &record.href,
+                    &record.text,
+                    record.is_active,
+                )?;
+
+                stats.n_links += 1;
+
+                if is_new {
+                    stats.n_new_links += 1;
+                }
+
+                let mut timestamps = vec![&record.first_seen];
+
+                if record.last_seen != record.first_seen {
+                    timestamps.push(&record.last_seen);
+                }
+
+                for timestamp in timestamps {
+                    #[rustfmt::skip]
+                    let n_inserted = tx
+                        .execute(
+                            "INSERT INTO links_collections \
+                             (collection_id, link_id, timestamp) \
+                             SELECT ?1, ?2, DATETIME(?3) \
+                             WHERE NOT EXISTS ( \
+                             SELECT 1 FROM links_collections \
+                             WHERE link_id = ?2 \
+                             AND timestamp = DATETIME(?3))",
+                            (collection_id, link_id, timestamp),
+                        )
+                        .context("database.import_records: INSERT")?;
+
+                    stats.n_observations += n_inserted as u64;
+                }
+            }
+
+            if stats.n_observations > 0 {
+                #[rustfmt::skip]
+                tx.execute(
+                    "UPDATE collections \
+                     SET n_links = ?1, n_new_links = ?2 \
+                     WHERE id = ?3",
+                    (stats.n_links, stats.n_new_links, collection_id),
+                )
+                .context("database.import_records: UPDATE")?;
+            } else {
+                tx.execute(
+                    "DELETE FROM collections WHERE id = ?1",
+                    (collection_id,),
+                )
+                .context("database.import_records: DELETE")?;
+            }
+
+            tx.commit().context("database.import_records: COMMIT")?;
+
+            Ok(stats)
+        })
+        .await?
+    }
+
+    /// Merges the pages, links, collections, and observations of
+    /// another Kairos database into this one in a single transaction.
+    ///
+    /// Pages are matched by URL and extract, links by page, href, and
+    /// text, and collections by start and end time.  Observations that
+    /// already exist for the same link and either the same collection
+    /// or the same timestamp are skipped.
+    pub async fn import_database(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<ImportStats> {
+        let connection = self.writer.clone();
+        let path = path.as_ref().to_path_buf();
+
+        tokio::task::spawn_blocking(move || {
+            let source = Connection::open_with_flags(
+                &path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY,
+            )
+            .with_context(|| format!("{path:?}"))?;
+
+            let mut connection = connection.blocking_lock();
+            let tx = connection
+                .transaction()
+                .context("database.import_database: BEGIN")?;
+            let mut stats = ImportStats::default();
+            let mut page_ids = HashMap::new();
+            let mut link_ids = HashMap::new();
+            let mut collection_ids = HashMap::new();
+
+            let mut statement = source
+                .prepare("SELECT id, url, extract FROM pages")
+                .context("database.import_database: SELECT")?;
+            let mut rows = statement.query(())?;
+
+            while let Some(row) = rows.next()? {
+                let url: String = row.get(1)?;
+                let extract: String = row.get(2)?;
+
+                page_ids.insert(
+                    row.get::<_, i64>(0)?,
+                    upsert_page(&tx, &url, &extract)?,
+                );
+            }
+
+            #[rustfmt::skip]
+            let mut statement = source
+                .prepare(
+                    "SELECT id, page_id, href, text, is_active \
+                     FROM links",
+                )
+                .context("database.import_database: SELECT")?;
+            let mut rows = statement.query(())?;
+
+            while let Some(row) = rows.next()? {
+                let source_page_id: i64 = row.get(1)?;
+                let href: String = row.get(2)?;
+                let text: String = row.get(3)?;
+                let Some(page_id) = page_ids.get(&source_page_id)
+                else {
+                    bail!(
+                        "database.import_database: \
+                         link refers to missing page {source_page_id}"
+                    );
+                };
+                let (link_id, is_new) = upsert_link(
+                    &tx,
+                    *page_id,
+                    &href,
+                    &text,
+                    row.get(4)?,
+                )?;
+
+                stats.n_links += 1;
+
+                if is_new {
+                    stats.n_new_links += 1;
+                }
+
+                link_ids.insert(row.get::<_, i64>(0)?, link_id);
+            }
+
+            #[rustfmt::skip]
+            let mut statement = source
+                .prepare(
+                    "SELECT id, start_time, end_time, \
+                     n_pages, n_links, n_new_links \
+                     FROM collections",
+                )
+                .context("database.import_database: SELECT")?;
+            let mut rows = statement.query(())?;
+
+            while let Some(row) = rows.next()? {
+                let start_time: Option<String> = row.get(1)?;
+                let end_time: Option<String> = row.get(2)?;
+
+                #[rustfmt::skip]
+                let existing: Option<i64> = tx
+                    .query_row(
+                        "SELECT id FROM collections \
+                         WHERE start_time IS ?1 AND end_time IS ?2",
+                        (&start_time, &end_time),
+                        |row| row.get(0),
+                    )
+                    .optional()
+                    .context("database.import_database: SELECT")?;
+
+                let collection_id = match existing {
+                    Some(x) => x,
+                    None => {
+                        #[rustfmt::skip]
+                        tx.execute(
+                            "INSERT INTO collections \
+                             (start_time, end_time, \
+                             n_pages, n_links, n_new_links) \
+                             VALUES (?1, ?2, ?3, ?4, ?5)",
+                            (
+                                &start_time,
+                                &end_time,
+                                row.get::<_, Option<i64>>(3)?,
+                                row.get::<_, Option<i64>>(4)?,
+                                row.get::<_, Option<i64>>(5)?,
+                            ),
+                        )
+                        .context("database.import_database: INSERT")?;
+
+                        tx.last_insert_rowid()
+                    }
+                };
+
+                collection_ids
+                    .insert(row.get::<_, i64>(0)?, collection_id);
+            }
+
+            #[rustfmt::skip]
+            let mut statement = source
+                .prepare(
+                    "SELECT collection_id, link_id, timestamp \
+                     FROM links_collections",
+                )
+                .context("database.import_database: SELECT")?;
+            let mut rows = statement.query(())?;
+
+            while let Some(row) = rows.next()? {
+                let collection_id = row
+                    .get::<_, Option<i64>>(0)?
+                    .and_then(|x| collection_ids.get(&x));
+                let Some(link_id) = row
+                    .get::<_, Option<i64>>(1)?
+                    .and_then(|x| link_ids.get(&x))
+                else {
+                    continue;
+                };
+                let timestamp: Option<String> = row.get(2)?;
+
+                #[rustfmt::skip]
+                let n_inserted = tx
+                    .execute(
+                        "INSERT INTO links_collections \
+                         (collection_id, link_id, timestamp) \
+                         SELECT ?1, ?2, ?3 \
+                         WHERE NOT EXISTS ( \
+                         SELECT 1 FROM links_collections \
+                         WHERE link_id = ?2 \
+                         AND (collection_id IS ?1 OR timestamp IS ?3))",
+                        (collection_id, link_id, &timestamp),
+                    )
+                    .context("database.import_database: INSERT")?;
+
+                stats.n_observations += n_inserted as u64;
+            }
+
+            tx.commit().context("database.import_database: COMMIT")?;
+
+            Ok(stats)
+        })
+        .await?
+    }
+}
+
+fn schema_version(connection: &Connection) -> Result<i64> {
+    connection
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .context("database: PRAGMA user_version")
+}
+
+/// Inserts the page unless it exists and returns its ID.
+fn upsert_page(
+    tx: &Transaction,
+    url: &str,
+    extract: &str,
+) -> Result<i64> {
+    #[rustfmt::skip]
+    tx.execute(
+        "INSERT OR IGNORE INTO pages (url, extract) VALUES (?1, ?2)",
+        (url, extract),
+    )
+    .context("database.upsert_page: INSERT OR IGNORE")?;
+
+    #[rustfmt::skip]
+    let page_id = tx
+        .query_row(
+            "SELECT id FROM pages WHERE url = ?1 AND extract = ?2",
+            (url, extract),
+            |row| row.get(0),
+        )
+        .context("database.upsert_page: SELECT")?;
+
+    Ok(page_id)
+}
+
+/// Inserts the link unless it exists and returns its ID along with
+/// whether it was inserted.
+fn upsert_link(
+    tx: &Transaction,
+    page_id: i64,
+    href: &str,
+    text: &str,
+    is_active: bool,
+) -> Result<(i64, bool)> {
+    #[rustfmt::skip]
+    let n_inserted = tx
+        .execute(
+            "INSERT OR IGNORE INTO links \
+             (page_id, href, text, is_active) \
+             VALUES (?1, ?2, ?3, ?4)",
+            (page_id, href, text, is_active),
+        )
+        .context("database.upsert_link: INSERT OR IGNORE")?;
+
+    #[rustfmt::skip]
+    let link_id = tx
+        .query_row(
+            "SELECT id FROM links \
+             WHERE page_id = ?1 AND href = ?2 AND text = ?3",
+            (page_id, href, text),
+            |row| row.get(0),
+        )
+        .context("database.upsert_link: SELECT")?;
+
+    Ok((link_id, n_inserted > 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scraper::selector::Selector;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn add_page_does_not_add_duplicate() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+
+        let id_a = db.add_page("http://foo.bar", &ex).await.unwrap();
+        let id_b = db.add_page("http://foo.bar", &ex).await.unwrap();
+
+        assert_eq!(id_a, id_b);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn add_page_accounts_for_url() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+
+        let id_a = db.add_page("http://foo/bar", &ex).await.unwrap();
+        let id_b = db.add_page("http://foo/baz", &ex).await.unwrap();
+
+        assert_ne!(id_a, id_b);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn add_page_accounts_for_extract() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel_a = Selector::parse("a[href^='/foo']").unwrap();
+        let sel_b = Selector::parse("a[href^='/bar']").unwrap();
+        let ex_a = Extract::CSSPlain(sel_a);
+        let ex_b = Extract::CSSPlain(sel_b);
+
+        let id_a = db.add_page("http://foo.bar", &ex_a).await.unwrap();
+        let id_b = db.add_page("http://foo.bar", &ex_b).await.unwrap();
+
+        assert_ne!(id_a, id_b);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn record_links_requires_valid_page_id() {
+        let db = Database::try_new(":memory:").unwrap();
+        let collection_id = db.start_collection().await.unwrap();
+        let nonexistent = 1;
+
+        assert!(db
+            .record_links(nonexistent, collection_id, links(&["/foo"]))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn record_links_does_not_add_duplicate() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+        let collection_id = db.start_collection().await.unwrap();
+
+        let result = db
+            .record_links(
+                page_id,
+                collection_id,
+                links(&["/foo", "/foo"]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(statuses(&result), [true, false]);
+
+        let collection_id = db.start_collection().await.unwrap();
+        let result = db
+            .record_links(page_id, collection_id, links(&["/foo"]))
+            .await
+            .unwrap();
+
+        assert_eq!(statuses(&result), [false]);
+        assert_eq!(db.link_records(None).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn record_links_reports_new_links() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_a = db.add_page("http://foo/bar", &ex).await.unwrap();
+        let page_b = db.add_page("http://foo/baz", &ex).await.unwrap();
+
+        let collection_id = db.start_collection().await.unwrap();
+        let result = db
+            .record_links(page_a, collection_id, links(&["/foo"]))
+            .await
+            .unwrap();
+
+        assert_eq!(statuses(&result), [true]);
+
+        let collection_id = db.start_collection().await.unwrap();
+        let result = db
+            .record_links(
+                page_a,
+                collection_id,
+                links(&["/foo", "/bar"]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(statuses(&result), [false, true]);
+
+        let result = db
+            .record_links(
+                page_b,
+                collection_id,
+                links(&["/foo", "/bar"]),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(statuses(&result), [true, true]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn record_links_updates_active_links() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+
+        let collection_id = db.start_collection().await.unwrap();
+
+        db.record_links(page_id, collection_id, links(&["/foo"]))
+            .await
+            .unwrap();
+
+        let records = db.link_records(None).await.unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert!(records[0].is_active);
+
+        let collection_id = db.start_collection().await.unwrap();
+
+        db.record_links(page_id, collection_id, links(&["/bar"]))
+            .await
+            .unwrap();
+
+        let records = db.link_records(None).await.unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert!(!records[0].is_active);
+        assert!(records[1].is_active);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn page_has_history_works() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_a = db.add_page("http://foo/bar", &ex).await.unwrap();
+        let page_b = db.add_page("http://foo/baz", &ex).await.unwrap();
+
+        assert!(!db.page_has_history(page_a).await.unwrap());
+
+        let collection_id = db.start_collection().await.unwrap();
+
+        db.record_links(page_a, collection_id, links(&["/foo"]))
+            .await
+            .unwrap();
+
+        assert!(db.page_has_history(page_a).await.unwrap());
+        assert!(!db.page_has_history(page_b).await.unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn page_has_history_without_links() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo/bar", &ex).await.unwrap();
+        let collection_id = db.start_collection().await.unwrap();
+
+        db.record_links(page_id, collection_id, links(&[]))
+            .await
+            .unwrap();
+
+        assert!(!db.page_has_history(page_id).await.unwrap());
+
+        db.set_baselined(page_id).await.unwrap();
+
+        assert!(db.page_has_history(page_id).await.unwrap());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn cache_validators_are_stored_per_page() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_a = db.add_page("http://foo/bar", &ex).await.unwrap();
+        let page_b = db.add_page("http://foo/baz", &ex).await.unwrap();
+        let validators = CacheValidators {
+            etag: Some("\"abc\"".to_string()),
+            last_modified: None,
+        };
+
+        db.set_cache_validators(page_a, validators.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.cache_validators(page_a).await.unwrap(),
+            validators
+        );
+        assert_eq!(
+            db.cache_validators(page_b).await.unwrap(),
+            CacheValidators::default()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn reobserve_links_records_latest_links() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+
+        for hrefs in [&["/foo", "/bar"][..], &["/bar", "/baz"]] {
+            let collection_id = db.start_collection().await.unwrap();
+
+            db.record_links(page_id, collection_id, links(hrefs))
+                .await
+                .unwrap();
+        }
+
+        let collection_id = db.start_collection().await.unwrap();
+
+        assert_eq!(
+            db.reobserve_links(page_id, collection_id).await.unwrap(),
+            2
+        );
+
+        let connection = db.writer.lock().await;
+        let mut statement = connection
+            .prepare(
+                "SELECT links.href FROM links_collections \
+                 JOIN links ON links.id = links_collections.link_id \
+                 WHERE collection_id = ?1 ORDER BY links.id",
+            )
+            .unwrap();
+        let hrefs = statement
+            .query_map((collection_id,), |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(hrefs, ["/bar", "/baz"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn content_hash_is_stored_per_page() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+
+        assert_eq!(db.content_hash(page_id).await.unwrap(), None);
+
+        db.set_content_hash(page_id, "abc".to_string())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.content_hash(page_id).await.unwrap().as_deref(),
+            Some("abc")
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn link_records_filters_by_first_seen() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+        let collection_id = db.start_collection().await.unwrap();
+
+        db.record_links(
+            page_id,
+            collection_id,
+            vec![Link {
+                href: "/foo".to_string(),
+                text: "bar".to_string(),
+            }],
+        )
+        .await
+        .unwrap();
+
+        let records = db.link_records(None).await.unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].url, "http://foo.bar");
+        assert_eq!(records[0].href, "/foo");
+        assert_eq!(records[0].text, "bar");
+        assert_eq!(records[0].first_seen, records[0].last_seen);
+
+        let records =
+            db.link_records(Some("2000-01-01")).await.unwrap();
+
+        assert_eq!(records.len(), 1);
+
+        let records =
+            db.link_records(Some("9999-01-01")).await.unwrap();
+
+        assert!(records.is_empty());
+        assert!(db.link_records(Some("yesterday")).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn import_records_does_not_add_duplicate() {
+        let db = Database::try_new(":memory:").unwrap();
+        let record = || LinkRecord {
+            page: None,
+            url: "http://foo.bar".to_string(),
+            extract: "CSS { \"a\" }".to_string(),
+            href: "/foo".to_string(),
+            text: "bar".to_string(),
+            first_seen: "2025-01-01 00:00:00".to_string(),
+            last_seen: "2025-02-01 00:00:00".to_string(),
+            is_active: true,
+        };
+
+        let stats = db.import_records(vec![record()]).await.unwrap();
+
+        assert_eq!(stats.n_links, 1);
+        assert_eq!(stats.n_new_links, 1);
+        assert_eq!(stats.n_observations, 2);
+
+        let stats = db.import_records(vec![record()]).await.unwrap();
+
+        assert_eq!(stats.n_links, 1);
+        assert_eq!(stats.n_new_links, 0);
+        assert_eq!(stats.n_observations, 0);
+
+        let records = db.link_records(None).await.unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].first_seen, "2025-01-01 00:00:00");
+        assert_eq!(records[0].last_seen, "2025-02-01 00:00:00");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn import_database_merges_observations_once() {
+        let path = std::env::temp_dir().join(format!(
+            "kairos-import-database-{}.db",
+            std::process::id()
+        ));
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+
+        {
+            let source = Database::try_new(&path).unwrap();
+            let page_id =
+                source.add_page("http://foo.bar", &ex).await.unwrap();
+
+            for (hrefs, date) in [
+                (&["/a", "/b"][..], "2025-01-01 00:00:00"),
+                (&["/b", "/c"], "2025-02-01 00:00:00"),
+            ] {
+                let collection_id =
+                    source.start_collection().await.unwrap();
+
+                source
+                    .record_links(page_id, collection_id, links(hrefs))
+                    .await
+                    .unwrap();
+                source
+                    .writer
+                    .lock()
+                    .await
+                    .execute_batch(&format!(
+                        "UPDATE collections SET start_time = '{date}' \
+                         WHERE id = {collection_id};
+                         UPDATE links_collections SET timestamp = '{date}' \
+                         WHERE collection_id = {collection_id};"
+                    ))
+                    .unwrap();
+            }
+        }
+
+        // The pages, links, and collections of the target database get
+        // different IDs than those in the source database.
+        //
+        let db = Database::try_new(":memory:").unwrap();
+        let page_id = db.add_page("http://other", &ex).await.unwrap();
+        let collection_id = db.start_collection().await.unwrap();
+
+        db.record_links(page_id, collection_id, links(&["/z"]))
+            .await
+            .unwrap();
+
+        let first = db.import_database(&path).await.unwrap();
+        let second = db.import_database(&path).await.unwrap();
+
+        for suffix in ["", "-wal", "-shm"] {
+            let mut x = path.clone().into_os_string();
+            x.push(suffix);
+            let _ = std::fs::remove_file(x);
+        }
+
+        assert_eq!(
+            (first.n_links, first.n_new_links, first.n_observations),
+            (3, 3, 4)
+        );
+        assert_eq!(
+            (second.n_links, second.n_new_links, second.n_observations),
+            (3, 0, 0)
+        );
+
+        let mut records = db
+            .link_records(None)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|x| x.url == "http://foo.bar")
+            .map(|x| (x.href, x.first_seen, x.last_seen))
+            .collect::<Vec<_>>();
+
+        records.sort();
+
+        assert_eq!(
+            records,
+            [
+                (
+                    "/a".to_string(),
+                    "2025-01-01 00:00:00".to_string(),
+                    "2025-01-01 00:00:00".to_string()
+                ),
+                (
+                    "/b".to_string(),
+                    "2025-01-01 00:00:00".to_string(),
+                    "2025-02-01 00:00:00".to_string()
+                ),
+                (
+                    "/c".to_string(),
+                    "2025-02-01 00:00:00".to_string(),
+                    "2025-02-01 00:00:00".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn migrate_applies_pending_migrations() {
+        let db = Database::open(":memory:").unwrap();
+
+        assert_eq!(
+            db.pending_migrations().unwrap().len(),
+            MIGRATIONS.len()
+        );
+        assert_eq!(db.migrate().unwrap().len(), MIGRATIONS.len());
+        assert!(db.pending_migrations().unwrap().is_empty());
+        assert!(db.migrate().unwrap().is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn open_refuses_newer_schema() {
+        let path = std::env::temp_dir().join(format!(
+            "kairos-open-refuses-newer-schema-{}.db",
+            std::process::id()
+        ));
+        let latest = MIGRATIONS.last().unwrap().version;
+
+        Connection::open(&path)
+            .unwrap()
+            .pragma_update(None, "user_version", latest + 1)
+            .unwrap();
+
+        let result = Database::open(&path);
+
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn prune_keeps_first_and_last_observation() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+
+        for _ in 0..3 {
+            let collection_id = db.start_collection().await.unwrap();
+
+            db.record_links(page_id, collection_id, links(&["/foo"]))
+                .await
+                .unwrap();
+        }
+
+        let retention = Retention {
+            keep_first_and_last: true,
+            ..Default::default()
+        };
+        let stats = db.prune(&retention).await.unwrap();
+
+        assert_eq!(stats.n_observations, 1);
+        assert_eq!(db.link_records(None).await.unwrap().len(), 1);
+
+        let stats = db.prune(&retention).await.unwrap();
+
+        assert_eq!(stats.n_observations, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn prune_deletes_only_inactive_links() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+
+        let collection_id = db.start_collection().await.unwrap();
+
+        db.record_links(
+            page_id,
+            collection_id,
+            links(&["/foo", "/bar"]),
+        )
+        .await
+        .unwrap();
+
+        let collection_id = db.start_collection().await.unwrap();
+
+        db.record_links(page_id, collection_id, links(&["/foo"]))
+            .await
+            .unwrap();
+
+        let retention = Retention {
+            keep_inactive_links_days: Some(0),
+            ..Default::default()
+        };
+
+        // Both links were observed today.
+        //
+        let stats = db.prune(&retention).await.unwrap();
+
+        assert_eq!(stats.n_links, 0);
+
+        db.writer
+            .lock()
+            .await
+            .execute(
+                "UPDATE links_collections \
+                 SET timestamp = DATETIME('now', 'utc', '-1 days')",
+                (),
+            )
+            .unwrap();
+
+        let stats = db.prune(&retention).await.unwrap();
+
+        assert_eq!(stats.n_links, 1);
+        assert_eq!(stats.n_observations, 1);
+
+        let records = db.link_records(None).await.unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].href, "/foo");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn readers_see_writes_but_cannot_write() {
+        let path = std::env::temp_dir().join(format!(
+            "kairos-readers-see-writes-{}.db",
+            std::process::id()
+        ));
+        let db = Database::try_new(&path).unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+        let collection_id = db.start_collection().await.unwrap();
+
+        db.record_links(page_id, collection_id, links(&["/foo"]))
+            .await
+            .unwrap();
+
+        assert_eq!(db.readers.len(), Database::N_READERS);
+        assert!(db.page_has_history(page_id).await.unwrap());
+        assert_eq!(db.link_records(None).await.unwrap().len(), 1);
+
+        let result =
+            db.reader().lock().await.execute("DELETE FROM links", ());
+
+        drop(db);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let mut x = path.clone().into_os_string();
+            x.push(suffix);
+            let _ = std::fs::remove_file(x);
+        }
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn prune_deletes_old_collections_and_page_runs() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+
+        for _ in 0..3 {
+            let collection_id = db.start_collection().await.unwrap();
+
+            db.record_links(page_id, collection_id, links(&["/foo"]))
+                .await
+                .unwrap();
+            db.add_page_run(collection_id, page_id, PageRun::default())
+                .await
+                .unwrap();
+            db.add_snapshot(collection_id, page_id, Vec::new())
+                .await
+                .unwrap();
+        }
+
+        db.writer
+            .lock()
+            .await
+            .execute(
+                "UPDATE collections \
+                 SET start_time = DATETIME('now', 'utc', '-10 days')",
+                (),
+            )
+            .unwrap();
+
+        let retention = Retention {
+            keep_collections_days: Some(1),
+            ..Default::default()
+        };
+        let stats = db.prune(&retention).await.unwrap();
+
+        assert_eq!(stats.n_observations, 1);
+        assert_eq!(stats.n_collections, 1);
+
+        let n_page_runs: i64 = db
+            .writer
+            .lock()
+            .await
+            .query_row("SELECT COUNT(*) FROM page_runs", (), |row| {
+                row.get(0)
+            })
+            .unwrap();
+
+        assert_eq!(n_page_runs, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn snapshots_are_stored_per_collection_and_page() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+        let collection_a = db.start_collection().await.unwrap();
+        let collection_b = db.start_collection().await.unwrap();
+
+        db.add_snapshot(collection_a, page_id, b"foo".to_vec())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.snapshot(collection_a, page_id).await.unwrap(),
+            Some(b"foo".to_vec())
+        );
+        assert_eq!(
+            db.snapshot(collection_b, page_id).await.unwrap(),
+            None
+        );
+        assert_eq!(db.delete_snapshots(1).await.unwrap(), 0);
+        assert_eq!(db.delete_snapshots(0).await.unwrap(), 0);
+
+        db.writer
+            .lock()
+            .await
+            .execute(
+                "UPDATE snapshots \
+                 SET timestamp = DATETIME('now', 'utc', '-10 days')",
+                (),
+            )
+            .unwrap();
+
+        assert_eq!(db.delete_snapshots(1).await.unwrap(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn recent_page_runs_are_newest_first() {
+        let db = Database::try_new(":memory:").unwrap();
+        let sel = Selector::parse("a").unwrap();
+        let ex = Extract::CSSPlain(sel);
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+
+        for n_links in 0..3 {
+            let collection_id = db.start_collection().await.unwrap();
+            let run = PageRun {
+                n_links: Some(n_links),
+                ..Default::default()
+            };
+
+            db.add_page_run(collection_id, page_id, run).await.unwrap();
+        }
+
+        let runs = db
+            .recent_page_runs("http://foo.bar", &ex, 2)
+            .await
+            .unwrap();
+
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].n_links, Some(2));
+        assert_eq!(runs[1].n_links, Some(1));
+        assert!(db
+            .recent_page_runs("http://foo.baz", &ex, 2)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    fn links(hrefs: &[&str]) -> Vec<Link> {
+        hrefs
+            .iter()
+            .map(|x| Link {
+                href: x.to_string(),
+                text: x.to_string(),
+            })
+            .collect()
+    }
 
-        assert!(db.link_exists(page_id, "/foo", "bar").await.unwrap());
-        assert!(db.link_exists(page_id, "/bar", "baz").await.unwrap());
+    fn statuses(result: &[(Link, bool)]) -> Vec<bool> {
+        result.iter().map(|(_, is_new)| *is_new).collect()
     }
 }
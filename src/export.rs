@@ -0,0 +1,264 @@
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::database::{Database, LinkRecord};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Format {
+    Csv,
+    Jsonl,
+    Json,
+}
+
+/// Writes the observed links to `output`, or to stdout if `output` is
+/// `None`.
+///
+/// Page names are looked up in the configuration by URL and extract.
+/// Links from pages that are no longer configured are exported without
+/// a page name.
+///
+/// # Errors
+///
+/// This function returns an error if:
+///
+/// - `since` is not a valid date or time,
+/// - `pages` contains a name that is not in the configuration, or
+/// - the output cannot be written.
+pub async fn export(
+    config: &Config,
+    database: &Database,
+    format: Format,
+    since: Option<&str>,
+    pages: &[String],
+    output: Option<&Path>,
+) -> Result<()> {
+    let records = records(config, database, since, pages).await?;
+
+    log::info!("exporting {} links", records.len());
+
+    let writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("export: {path:?}"))?,
+        ),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    write_records(&records, format, writer)
+}
+
+/// Returns the observed links along with the names of their pages,
+/// only from `pages` unless it is empty.
+async fn records(
+    config: &Config,
+    database: &Database,
+    since: Option<&str>,
+    pages: &[String],
+) -> Result<Vec<LinkRecord>> {
+    for name in pages {
+        if !config.page.iter().any(|page| &page.name == name) {
+            bail!("export: no page named {name:?}");
+        }
+    }
+
+    let page_names: HashMap<(String, String), String> = config
+        .page
+        .iter()
+        .map(|page| {
+            (
+                (page.url.clone(), page.extract.to_string()),
+                page.name.clone(),
+            )
+        })
+        .collect();
+
+    Ok(database
+        .link_records(since)
+        .await?
+        .into_iter()
+        .map(|mut record| {
+            record.page = page_names
+                .get(&(record.url.clone(), record.extract.clone()))
+                .cloned();
+            record
+        })
+        .filter(|record| {
+            pages.is_empty()
+                || record
+                    .page
+                    .as_ref()
+                    .is_some_and(|x| pages.contains(x))
+        })
+        .collect())
+}
+
+fn write_records(
+    records: &[LinkRecord],
+    format: Format,
+    mut writer: impl Write,
+) -> Result<()> {
+    match format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+
+            for record in records {
+                writer.serialize(record)?;
+            }
+
+            writer.flush()?;
+        }
+        Format::Jsonl => {
+            for record in records {
+                serde_json::to_writer(&mut writer, record)?;
+                writeln!(writer)?;
+            }
+
+            writer.flush()?;
+        }
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut writer, records)?;
+            writeln!(writer)?;
+            writer.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::Link;
+
+    fn record() -> LinkRecord {
+        LinkRecord {
+            page: Some("Acme".to_string()),
+            url: "http://foo.bar".to_string(),
+            extract: "CSS { \"a\" }".to_string(),
+            href: "/jobs/1".to_string(),
+            text: "Engineer, \"Senior\"".to_string(),
+            first_seen: "2025-01-01 00:00:00".to_string(),
+            last_seen: "2025-02-01 00:00:00".to_string(),
+            is_active: true,
+        }
+    }
+
+    fn written(format: Format) -> String {
+        let mut output = Vec::new();
+
+        write_records(&[record(), record()], format, &mut output)
+            .unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn write_records_writes_csv() {
+        let output = written(Format::Csv);
+        let lines = output.lines().collect::<Vec<_>>();
+
+        assert_eq!(
+            lines,
+            [
+                "page,url,extract,href,text,first_seen,last_seen,\
+                 is_active",
+                "Acme,http://foo.bar,\"CSS { \"\"a\"\" }\",/jobs/1,\
+                 \"Engineer, \"\"Senior\"\"\",2025-01-01 00:00:00,\
+                 2025-02-01 00:00:00,true",
+                "Acme,http://foo.bar,\"CSS { \"\"a\"\" }\",/jobs/1,\
+                 \"Engineer, \"\"Senior\"\"\",2025-01-01 00:00:00,\
+                 2025-02-01 00:00:00,true",
+            ]
+        );
+    }
+
+    #[test]
+    fn write_records_writes_jsonl() {
+        let output = written(Format::Jsonl);
+        let lines = output.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines.len(), 2);
+
+        for line in lines {
+            assert_eq!(
+                serde_json::from_str::<serde_json::Value>(line)
+                    .unwrap(),
+                serde_json::json!({
+                    "page": "Acme",
+                    "url": "http://foo.bar",
+                    "extract": "CSS { \"a\" }",
+                    "href": "/jobs/1",
+                    "text": "Engineer, \"Senior\"",
+                    "first_seen": "2025-01-01 00:00:00",
+                    "last_seen": "2025-02-01 00:00:00",
+                    "is_active": true
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn write_records_writes_json() {
+        let value: serde_json::Value =
+            serde_json::from_str(&written(Format::Json)).unwrap();
+        let records = value.as_array().unwrap();
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["href"], "/jobs/1");
+        assert_eq!(records[1]["page"], "Acme");
+        assert_eq!(records[1]["is_active"], true);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn records_are_filtered_by_page() {
+        let config: Config = toml::from_str(
+            "database = \"jobs.db\"\n\
+             [[page]]\nname = \"a\"\n\
+             url = \"http://foo.bar/a\"\nextract = \"a\"\n\
+             [[page]]\nname = \"b\"\n\
+             url = \"http://foo.bar/b\"\nextract = \"a\"\n",
+        )
+        .unwrap();
+        let database = Database::try_new(":memory:").unwrap();
+        let collection_id = database.start_collection().await.unwrap();
+
+        for (page, href) in config.page.iter().zip(["/1", "/2"]) {
+            let page_id = database
+                .add_page(&page.url, &page.extract)
+                .await
+                .unwrap();
+
+            database
+                .record_links(
+                    page_id,
+                    collection_id,
+                    vec![Link {
+                        href: href.to_string(),
+                        text: String::new(),
+                    }],
+                )
+                .await
+                .unwrap();
+        }
+
+        let pages = |x: &[&str]| {
+            x.iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
+        let all = records(&config, &database, None, &[]).await.unwrap();
+        let only_b = records(&config, &database, None, &pages(&["b"]))
+            .await
+            .unwrap();
+
+        assert_eq!(all.len(), 2);
+        assert_eq!(only_b.len(), 1);
+        assert_eq!(only_b[0].page.as_deref(), Some("b"));
+        assert_eq!(only_b[0].href, "/2");
+        assert!(records(&config, &database, None, &pages(&["c"]))
+            .await
+            .is_err());
+    }
+}
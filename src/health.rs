@@ -0,0 +1,194 @@
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::database::{Database, PageRun};
+use crate::page::Page;
+
+/// Settings for page health checks.  A page is unhealthy if it keeps
+/// failing, or if it suddenly yields no links or far fewer links than
+/// usual, which is typical of a redesigned site that the extract no
+/// longer matches.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Health {
+    /// Alert when a page has failed this many runs in a row.
+    #[serde(default = "default_max_failures")]
+    pub max_failures: usize,
+
+    /// Alert when a page that yielded links in its previous run yields
+    /// none.
+    #[serde(default = "default_zero_links")]
+    pub zero_links: bool,
+
+    /// Alert when the link count drops by more than this percentage
+    /// compared with the average of recent runs.
+    pub max_drop_percent: Option<f64>,
+
+    /// Number of recent successful runs that the average link count is
+    /// computed from.
+    #[serde(default = "default_history")]
+    pub history: usize,
+}
+
+fn default_max_failures() -> usize {
+    3
+}
+
+fn default_zero_links() -> bool {
+    true
+}
+
+fn default_history() -> usize {
+    10
+}
+
+/// Checks the health of each page based on its recent runs and returns
+/// a message for each problem.
+///
+/// Each problem is reported once, in the run in which it first occurs,
+/// so that a page that stays broken doesn't raise an alert in every
+/// collection.
+pub async fn check(
+    pages: &[Page],
+    database: &Database,
+    health: &Health,
+) -> Result<Vec<String>> {
+    let mut alerts = Vec::new();
+    let limit = health.max_failures.max(health.history) + 2;
+
+    for page in pages {
+        let runs = database
+            .recent_page_runs(&page.url, &page.extract, limit)
+            .await?;
+
+        for alert in evaluate(&runs, health) {
+            log::warn!(target: &page.name, "{alert}");
+            alerts.push(format!("{} {alert}", page.name));
+        }
+    }
+
+    Ok(alerts)
+}
+
+/// Evaluates the runs of a page, newest first.
+fn evaluate(runs: &[PageRun], health: &Health) -> Vec<String> {
+    let mut alerts = Vec::new();
+    let n_failures =
+        runs.iter().take_while(|x| x.error.is_some()).count();
+
+    if health.max_failures > 0 && n_failures == health.max_failures {
+        alerts.push(format!("has failed {n_failures} runs in a row."));
+    }
+
+    // Link counts are only checked if the latest run succeeded.
+    //
+    if n_failures > 0 {
+        return alerts;
+    }
+
+    let mut link_counts = runs
+        .iter()
+        .filter(|x| x.error.is_none())
+        .filter_map(|x| x.n_links);
+
+    let (Some(latest), Some(previous)) =
+        (link_counts.next(), link_counts.next())
+    else {
+        return alerts;
+    };
+
+    if health.zero_links && latest == 0 && previous > 0 {
+        alerts.push("yielded no links.".to_string());
+        return alerts;
+    }
+
+    if let Some(max_drop) = health.max_drop_percent {
+        let earlier = std::iter::once(previous)
+            .chain(link_counts)
+            .take(health.history)
+            .collect::<Vec<_>>();
+        let usual =
+            earlier.iter().sum::<u64>() as f64 / earlier.len() as f64;
+        let is_drop = |n: u64| {
+            usual > 0.0 && (usual - n as f64) / usual * 100.0 > max_drop
+        };
+
+        if is_drop(latest) && !is_drop(previous) {
+            alerts.push(format!(
+                "yielded {latest} links, down from {usual:.0} on average."
+            ));
+        }
+    }
+
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health() -> Health {
+        Health {
+            max_failures: 2,
+            zero_links: true,
+            max_drop_percent: Some(50.0),
+            history: 10,
+        }
+    }
+
+    fn ok(n_links: u64) -> PageRun {
+        PageRun {
+            n_links: Some(n_links),
+            ..Default::default()
+        }
+    }
+
+    fn failed() -> PageRun {
+        PageRun {
+            error: Some("HTTP status 404".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn healthy_page_raises_no_alert() {
+        assert!(
+            evaluate(&[ok(10), ok(9), ok(11)], &health()).is_empty()
+        );
+        assert!(evaluate(&[ok(10)], &health()).is_empty());
+        assert!(evaluate(&[], &health()).is_empty());
+    }
+
+    #[test]
+    fn repeated_failures_raise_one_alert() {
+        assert!(evaluate(&[failed(), ok(10)], &health()).is_empty());
+        assert_eq!(
+            evaluate(&[failed(), failed(), ok(10)], &health()).len(),
+            1
+        );
+        assert!(evaluate(&[failed(), failed(), failed()], &health())
+            .is_empty());
+    }
+
+    #[test]
+    fn zero_links_raise_one_alert() {
+        assert_eq!(evaluate(&[ok(0), ok(10)], &health()).len(), 1);
+        assert!(evaluate(&[ok(0), ok(0), ok(10)], &health()).is_empty());
+        assert_eq!(
+            evaluate(&[ok(0), failed(), ok(10)], &health()).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn link_count_drop_raises_one_alert() {
+        assert_eq!(
+            evaluate(&[ok(4), ok(10), ok(12), ok(8)], &health()).len(),
+            1
+        );
+        assert!(evaluate(&[ok(4), ok(4), ok(10), ok(12)], &health())
+            .is_empty());
+        assert!(
+            evaluate(&[ok(6), ok(10), ok(12)], &health()).is_empty()
+        );
+    }
+}
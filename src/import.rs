@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::io::BufRead;
+use std::path::Path;
+
+use crate::config::Config;
+use crate::database::{Database, ImportStats, LinkRecord};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Format {
+    Csv,
+    Jsonl,
+    Json,
+    Sqlite,
+}
+
+impl Format {
+    /// Guesses the format from the file extension.  Files with an
+    /// extension other than `.csv`, `.jsonl`, or `.json` are assumed
+    /// to be Kairos databases.
+    pub fn detect(path: &Path) -> Self {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("csv") => Self::Csv,
+            Some("jsonl") => Self::Jsonl,
+            Some("json") => Self::Json,
+            _ => Self::Sqlite,
+        }
+    }
+}
+
+/// Merges the links in an export file or in another Kairos database
+/// into `database`.
+///
+/// Records in export files that name a page in the configuration are
+/// mapped to that page's current URL and extract.  Other records, and
+/// pages in other databases, are mapped by URL and extract.
+///
+/// # Errors
+///
+/// This function returns an error if:
+///
+/// - the input cannot be read or parsed or
+/// - the database cannot be written.
+pub async fn import(
+    config: &Config,
+    database: &Database,
+    path: &Path,
+    format: Format,
+) -> Result<ImportStats> {
+    let stats = match format {
+        Format::Sqlite => database.import_database(path).await?,
+        Format::Csv => {
+            import_records(config, database, path, RecordFormat::Csv)
+                .await?
+        }
+        Format::Jsonl => {
+            import_records(config, database, path, RecordFormat::Jsonl)
+                .await?
+        }
+        Format::Json => {
+            import_records(config, database, path, RecordFormat::Json)
+                .await?
+        }
+    };
+
+    log::info!(
+        "imported {} links ({} new) and {} observations",
+        stats.n_links,
+        stats.n_new_links,
+        stats.n_observations
+    );
+
+    Ok(stats)
+}
+
+/// The formats of export files.
+#[derive(Debug, Clone, Copy)]
+enum RecordFormat {
+    Csv,
+    Jsonl,
+    Json,
+}
+
+async fn import_records(
+    config: &Config,
+    database: &Database,
+    path: &Path,
+    format: RecordFormat,
+) -> Result<ImportStats> {
+    let records = read_records(path, format)
+        .with_context(|| format!("import: {path:?}"))?
+        .into_iter()
+        .map(|record| map_page(config, record))
+        .collect();
+
+    database.import_records(records).await
+}
+
+fn read_records(
+    path: &Path,
+    format: RecordFormat,
+) -> Result<Vec<LinkRecord>> {
+    match format {
+        RecordFormat::Csv => Ok(csv::Reader::from_path(path)?
+            .deserialize()
+            .collect::<csv::Result<Vec<_>>>()?),
+        RecordFormat::Jsonl => {
+            let reader =
+                std::io::BufReader::new(std::fs::File::open(path)?);
+            let mut records = Vec::new();
+
+            for line in reader.lines() {
+                let line = line?;
+
+                if !line.trim().is_empty() {
+                    records.push(serde_json::from_str(&line)?);
+                }
+            }
+
+            Ok(records)
+        }
+        RecordFormat::Json => Ok(serde_json::from_reader(
+            std::io::BufReader::new(std::fs::File::open(path)?),
+        )?),
+    }
+}
+
+fn map_page(config: &Config, mut record: LinkRecord) -> LinkRecord {
+    if let Some(page) = config
+        .page
+        .iter()
+        .find(|page| Some(&page.name) == record.page.as_ref())
+    {
+        record.url = page.url.clone();
+        record.extract = page.extract.to_string();
+    }
+
+    record
+}
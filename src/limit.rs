@@ -0,0 +1,222 @@
+use http::Extensions;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_middleware::{Middleware, Next};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::{Mutex, Semaphore};
+use tokio::time::Instant;
+
+/// Settings that limit how many requests are made at once and how
+/// often the same host is requested.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct Limits {
+    /// Number of pages that are collected at the same time.
+    pub max_concurrency: Option<usize>,
+
+    /// Number of requests to the same host that are made at the same
+    /// time.
+    pub per_host: Option<usize>,
+
+    /// Minimum time between requests to the same host in seconds.
+    #[serde(default)]
+    pub host_delay: f64,
+}
+
+/// Middleware that enforces the per-host limits.  It also holds back
+/// requests to a host that responded with 429 (Too Many Requests) or
+/// 503 (Service Unavailable) for as long as the `Retry-After` header
+/// asks.
+///
+/// It is meant to be placed inside the retry middleware so that every
+/// attempt is subject to the limits.
+#[derive(Debug)]
+pub struct HostLimiter {
+    per_host: Option<usize>,
+    delay: Duration,
+    hosts: Mutex<HashMap<String, Arc<Host>>>,
+}
+
+#[derive(Debug)]
+struct Host {
+    permits: Option<Semaphore>,
+    schedule: Mutex<Schedule>,
+}
+
+#[derive(Debug)]
+struct Schedule {
+    next_request: Instant,
+    delay: Duration,
+}
+
+impl HostLimiter {
+    pub fn new(limits: &Limits) -> Self {
+        Self {
+            per_host: limits.per_host,
+            delay: Duration::try_from_secs_f64(limits.host_delay)
+                .unwrap_or_default(),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn host(&self, name: &str) -> Arc<Host> {
+        self.hosts
+            .lock()
+            .await
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                Arc::new(Host {
+                    permits: self
+                        .per_host
+                        .map(|x| Semaphore::new(x.max(1))),
+                    schedule: Mutex::new(Schedule {
+                        next_request: Instant::now(),
+                        delay: self.delay,
+                    }),
+                })
+            })
+            .clone()
+    }
+
+    /// Delays the next request to the host named `name` by at least
+    /// `delay` from now.
+    async fn hold_back(&self, name: &str, delay: Duration) {
+        let host = self.host(name).await;
+        let mut schedule = host.schedule.lock().await;
+
+        schedule.next_request =
+            schedule.next_request.max(Instant::now() + delay);
+    }
+
+    /// Raises the minimum time between requests to the host named
+    /// `name` to `delay`, e.g., for the `Crawl-delay` of its
+    /// robots.txt.
+    pub async fn set_delay(&self, name: &str, delay: Duration) {
+        let host = self.host(name).await;
+        let mut schedule = host.schedule.lock().await;
+
+        schedule.delay = self.delay.max(delay);
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for HostLimiter {
+    async fn handle(
+        &self,
+        request: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let Some(name) = request.url().host_str().map(str::to_string)
+        else {
+            return next.run(request, extensions).await;
+        };
+
+        let host = self.host(&name).await;
+        let _permit = match &host.permits {
+            Some(x) => {
+                Some(x.acquire().await.expect("semaphore is open"))
+            }
+            None => None,
+        };
+
+        // The lock is held while waiting so that requests that queue up
+        // for the same host are spaced out one after another.
+        //
+        {
+            let mut schedule = host.schedule.lock().await;
+
+            tokio::time::sleep_until(schedule.next_request).await;
+            schedule.next_request = Instant::now() + schedule.delay;
+        }
+
+        let response = next.run(request, extensions).await;
+
+        if let Ok(x) = &response
+            && matches!(
+                x.status(),
+                StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::SERVICE_UNAVAILABLE
+            )
+            && let Some(delay) = x
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|x| x.to_str().ok())
+                .and_then(|x| retry_after(x, SystemTime::now()))
+        {
+            log::info!(
+                "{name}: retry after {} seconds",
+                delay.as_secs()
+            );
+            self.hold_back(&name, delay).await;
+        }
+
+        response
+    }
+}
+
+/// Parses the value of a `Retry-After` header, which is either a
+/// number of seconds or an HTTP date (RFC 9110, section 10.2.3).  A
+/// date that has passed yields `None`.
+fn retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+
+    match value.parse() {
+        Ok(x) => Some(Duration::from_secs(x)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(now)
+            .ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_after_parses_seconds() {
+        let now =
+            httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT")
+                .unwrap();
+
+        assert_eq!(
+            retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after(" 5 ", now),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            retry_after("Wed, 21 Oct 2015 07:30:00 GMT", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            None
+        );
+        assert_eq!(retry_after("soon", now), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn hold_back_only_extends_the_delay() {
+        let limiter = HostLimiter::new(&Limits::default());
+
+        limiter
+            .hold_back("example.com", Duration::from_secs(60))
+            .await;
+        limiter
+            .hold_back("example.com", Duration::from_secs(1))
+            .await;
+
+        let host = limiter.host("example.com").await;
+        let next_request = host.schedule.lock().await.next_request;
+
+        assert!(
+            next_request > Instant::now() + Duration::from_secs(50)
+        );
+    }
+}
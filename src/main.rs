@@ -1,6 +1,8 @@
 use anyhow::Result;
 use clap::ArgAction;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tokio::signal::unix::SignalKind;
 use tokio_util::sync::CancellationToken;
@@ -8,15 +10,27 @@ use tokio_util::sync::CancellationToken;
 mod collection;
 mod config;
 mod database;
+mod export;
+mod health;
+mod import;
+mod limit;
 mod page;
+mod preset;
 mod pushover;
 mod request;
+mod robots;
+mod secret;
+mod snapshot;
+mod vars;
+mod watch;
 
 use crate::collection::Collection;
 use crate::config::Config;
-use crate::database::Database;
-use crate::page::Page;
+use crate::database::{Database, Retention};
 use crate::pushover::Pushover;
+use crate::request::Clients;
+use crate::snapshot::Snapshots;
+use crate::watch::Watcher;
 
 /// Command-line interface to open-webui.
 #[derive(Debug, Parser)]
@@ -27,13 +41,82 @@ struct Args {
     config: String,
 
     /// Set log level (-v for info, -vv for debug, -vvv for trace).
-    #[arg(long, short, action = ArgAction::Count)]
+    #[arg(long, short, global = true, action = ArgAction::Count)]
     verbose: u8,
+
+    /// Reload the configuration when it or an included file changes.
+    #[arg(long)]
+    watch: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Export observed links.
+    Export {
+        /// Output format.
+        #[arg(long, short, value_enum, default_value = "csv")]
+        format: export::Format,
+
+        /// Only export links first seen at or after this date or time
+        /// (e.g., 2025-01-31 or "2025-01-31 12:00:00", in UTC).
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Only export links from this page (may be repeated).
+        #[arg(long)]
+        page: Vec<String>,
+
+        /// Write to this file instead of stdout.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Import links from an export file or another Kairos database.
+    Import {
+        /// Input format (guessed from the file extension by default).
+        #[arg(long, short, value_enum)]
+        format: Option<import::Format>,
+
+        /// Path to the export file or database.
+        path: PathBuf,
+    },
+
+    /// Extract links from the page snapshots of a collection with the
+    /// current configuration.
+    Reextract {
+        /// ID of the collection.
+        #[arg(long)]
+        collection: i64,
+    },
+
+    /// Maintain the database.
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum DbCommand {
+    /// Apply pending schema migrations.
+    Migrate {
+        /// List the pending migrations without applying them.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Delete collection history according to the retention settings
+    /// and return free space to the file system.
+    Prune,
 }
 
 async fn send_notification(
     collection: &Collection,
     pushover: &Pushover,
+    clients: &Clients,
     cancellation_token: CancellationToken,
 ) -> Result<()> {
     let mut n_pages = 0;
@@ -88,52 +171,295 @@ async fn send_notification(
     };
 
     pushover
-        .send(&message, Some(&title), cancellation_token)
+        .send(&message, Some(&title), clients, cancellation_token)
+        .await?;
+
+    Ok(())
+}
+
+async fn prune(
+    database: &Database,
+    retention: &Retention,
+) -> Result<database::PruneStats> {
+    let stats = database.prune(retention).await?;
+
+    log::info!(
+        "pruned {} links, {} observations, and {} collections",
+        stats.n_links,
+        stats.n_observations,
+        stats.n_collections
+    );
+
+    database.vacuum().await?;
+
+    Ok(stats)
+}
+
+async fn send_health_notification(
+    alerts: &[String],
+    pushover: &Pushover,
+    clients: &Clients,
+    cancellation_token: CancellationToken,
+) -> Result<()> {
+    let title = if alerts.len() > 1 {
+        format!("{} page problems", alerts.len())
+    } else {
+        "1 page problem".to_string()
+    };
+
+    pushover
+        .send(
+            &alerts.join("\n"),
+            Some(&title),
+            clients,
+            cancellation_token,
+        )
         .await?;
 
     Ok(())
 }
 
 async fn collect_and_notify(
-    pages: &[Page],
+    config: &Config,
     database: &Database,
-    pushover: Option<&Pushover>,
+    clients: &Clients,
     cancellation_token: CancellationToken,
 ) -> Result<()> {
     let collection = Collection::try_new(
-        pages,
+        config,
         database,
+        clients,
         cancellation_token.clone(),
     )
     .await?;
 
     if collection.stats.n_new_links > 0
-        && let Some(x) = pushover
+        && let Some(x) = &config.pushover
+    {
+        // A notifier outage shouldn't keep health checks and pruning
+        // from running.
+        //
+        if let Err(error) = send_notification(
+            &collection,
+            x,
+            clients,
+            cancellation_token.clone(),
+        )
+        .await
+        {
+            log::error!("notification: {error:#}");
+        }
+    }
+
+    // The pages of a cancelled collection would look as if they had
+    // failed.
+    //
+    if let Some(health) = &config.health
+        && !cancellation_token.is_cancelled()
     {
-        send_notification(&collection, x, cancellation_token).await?;
+        let alerts =
+            health::check(&config.page, database, health).await?;
+
+        if !alerts.is_empty()
+            && let Some(x) = &config.pushover
+            && let Err(error) = send_health_notification(
+                &alerts,
+                x,
+                clients,
+                cancellation_token,
+            )
+            .await
+        {
+            log::error!("health notification: {error:#}");
+        }
+    }
+
+    if config.retention.is_enabled() {
+        prune(database, &config.retention).await?;
+    }
+
+    if let Some(x) = &config.snapshots {
+        prune_snapshots(x, database).await?;
     }
 
     Ok(())
 }
 
+async fn prune_snapshots(
+    snapshots: &Snapshots,
+    database: &Database,
+) -> Result<u64> {
+    let n_snapshots = snapshots.prune(database).await?;
+
+    log::info!("pruned {n_snapshots} snapshots");
+
+    Ok(n_snapshots)
+}
+
+fn open_database(path: &Path) -> Option<Database> {
+    log::info!("opening database {path:?}");
+
+    match Database::try_new(path) {
+        Ok(x) => Some(x),
+        Err(x) => {
+            log::error!("database: {x:#}");
+            None
+        }
+    }
+}
+
+/// Loads the configuration again.  If it is valid, it replaces `config`,
+/// the HTTP clients are rebuilt, and the database is reopened if its
+/// path has changed.  Otherwise, the current configuration is kept.
+/// Returns whether the configuration was replaced.
+fn reload(
+    args: &Args,
+    config: &mut Config,
+    database: &mut Option<Database>,
+    clients: &mut Clients,
+) -> bool {
+    let new_config = match Config::load(&args.config) {
+        Ok(x) => x,
+        Err(x) => {
+            log::error!("{x:#}");
+            return false;
+        }
+    };
+    let new_clients = match Clients::new(&new_config) {
+        Ok(x) => x,
+        Err(x) => {
+            log::error!("{x:#}");
+            return false;
+        }
+    };
+
+    log::info!(
+        "loaded {} pages from {} files",
+        new_config.page.len(),
+        new_config.sources.len()
+    );
+    log_page_changes(config, &new_config);
+
+    if new_config.database != config.database {
+        *database = open_database(&new_config.database);
+    }
+
+    *config = new_config;
+    *clients = new_clients;
+
+    true
+}
+
+/// A difference between the pages of two configurations.
+#[derive(Debug, PartialEq)]
+enum PageChange<'a> {
+    Added(&'a str),
+    Changed(&'a str),
+    Removed(&'a str),
+}
+
+/// Logs which pages were added, removed or changed between two
+/// configurations.
+fn log_page_changes(old: &Config, new: &Config) {
+    for change in page_changes(old, new) {
+        match change {
+            PageChange::Added(x) => log::info!("added page {x:?}"),
+            PageChange::Changed(x) => log::info!("changed page {x:?}"),
+            PageChange::Removed(x) => log::info!("removed page {x:?}"),
+        }
+    }
+}
+
+/// Returns the pages of `new` that aren't in `old` or differ from it,
+/// followed by the pages of `old` that aren't in `new`.
+fn page_changes<'a>(
+    old: &'a Config,
+    new: &'a Config,
+) -> Vec<PageChange<'a>> {
+    let old_pages: HashMap<_, _> = old
+        .page
+        .iter()
+        .map(|x| (&x.name, x.fingerprint()))
+        .collect();
+    let new_pages: HashMap<_, _> = new
+        .page
+        .iter()
+        .map(|x| (&x.name, x.fingerprint()))
+        .collect();
+    let mut changes = Vec::new();
+
+    for page in &new.page {
+        match old_pages.get(&page.name) {
+            None => changes.push(PageChange::Added(&page.name)),
+            Some(x) if *x != new_pages[&page.name] => {
+                changes.push(PageChange::Changed(&page.name));
+            }
+            Some(_) => {}
+        }
+    }
+
+    for page in &old.page {
+        if !new_pages.contains_key(&page.name) {
+            changes.push(PageChange::Removed(&page.name));
+        }
+    }
+
+    changes
+}
+
+fn start_watcher(config: &Config) -> Option<Watcher> {
+    match Watcher::new(config) {
+        Ok(x) => Some(x),
+        Err(x) => {
+            log::error!("watch: {x:#}");
+            None
+        }
+    }
+}
+
 async fn process(args: &Args) -> Result<()> {
     let mut config = Config::load(&args.config)?;
+    let mut database = open_database(&config.database);
+    let mut clients = Clients::new(&config)?;
     let mut sighup = tokio::signal::unix::signal(SignalKind::hangup())?;
     let mut sigusr1 =
         tokio::signal::unix::signal(SignalKind::user_defined1())?;
     let mut current_task: Option<CancellationToken> = None;
+    let mut watcher = if args.watch {
+        start_watcher(&config)
+    } else {
+        None
+    };
 
     const DUR_24_HOURS: u64 = 24 * 60 * 60;
     let mut interval =
         tokio::time::interval(Duration::from_secs(DUR_24_HOURS));
 
+    // How long to wait before trying again if the database cannot be
+    // opened.
+    const DUR_RETRY: Duration = Duration::from_secs(60);
+
     loop {
         tokio::select! {
             _ = sighup.recv() => {
                 log::info!("reloading config from {:?}", args.config);
-                match Config::load(&args.config) {
-                    Ok(x) => config = x,
-                    Err(x) => log::error!("{x}"),
+
+                if reload(args, &mut config, &mut database, &mut clients)
+                    && args.watch
+                {
+                    watcher = start_watcher(&config);
+                }
+            },
+            _ = async {
+                match &mut watcher {
+                    Some(x) => x.changed(&config).await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                log::info!("config changed; reloading");
+
+                if reload(args, &mut config, &mut database, &mut clients) {
+                    watcher = start_watcher(&config);
                 }
             },
             _ = sigusr1.recv() => match current_task {
@@ -147,9 +473,21 @@ async fn process(args: &Args) -> Result<()> {
                 }
             },
             _ = interval.tick() => {
-                let pages = config.page.clone();
-                let pushover = config.pushover.clone();
-                let database = Database::try_new(&config.database)?;
+                if database.is_none() {
+                    database = open_database(&config.database);
+                }
+
+                let Some(database) = database.clone() else {
+                    log::error!(
+                        "database unavailable; retrying in {} seconds",
+                        DUR_RETRY.as_secs()
+                    );
+                    interval.reset_after(DUR_RETRY);
+                    continue;
+                };
+
+                let config = config.clone();
+                let clients = clients.clone();
 
                 if let Some(token) = current_task {
                     log::info!(
@@ -163,9 +501,9 @@ async fn process(args: &Args) -> Result<()> {
 
                 tokio::task::spawn(async move {
                     if let Err(x) = collect_and_notify(
-                        &pages,
+                        &config,
                         &database,
-                        pushover.as_ref(),
+                        &clients,
                         token_clone,
                     ).await {
                         log::error!("collection: {x}");
@@ -178,6 +516,101 @@ async fn process(args: &Args) -> Result<()> {
     }
 }
 
+async fn run(args: &Args) -> Result<()> {
+    match &args.command {
+        None => process(args).await,
+        Some(Command::Export {
+            format,
+            since,
+            page,
+            output,
+        }) => {
+            let config = Config::load(&args.config)?;
+            let database = Database::try_new(&config.database)?;
+
+            export::export(
+                &config,
+                &database,
+                *format,
+                since.as_deref(),
+                page,
+                output.as_deref(),
+            )
+            .await
+        }
+        Some(Command::Import { format, path }) => {
+            let config = Config::load(&args.config)?;
+            let database = Database::try_new(&config.database)?;
+            let format =
+                format.unwrap_or_else(|| import::Format::detect(path));
+            let stats =
+                import::import(&config, &database, path, format)
+                    .await?;
+
+            println!(
+                "Imported {} links ({} new) and {} observations.",
+                stats.n_links, stats.n_new_links, stats.n_observations
+            );
+
+            Ok(())
+        }
+        Some(Command::Reextract { collection }) => {
+            let config = Config::load(&args.config)?;
+            let database = Database::try_new(&config.database)?;
+
+            snapshot::reextract(&config, &database, *collection).await
+        }
+        Some(Command::Db {
+            command: DbCommand::Migrate { dry_run },
+        }) => {
+            let config = Config::load(&args.config)?;
+            let database = Database::open(&config.database)?;
+            let migrations = if *dry_run {
+                database.pending_migrations()?
+            } else {
+                database.migrate()?
+            };
+
+            if migrations.is_empty() {
+                println!("No pending migrations.");
+            }
+
+            for migration in migrations {
+                println!("{:04} {}", migration.version, migration.name);
+            }
+
+            Ok(())
+        }
+        Some(Command::Db {
+            command: DbCommand::Prune,
+        }) => {
+            let config = Config::load(&args.config)?;
+            let database = Database::try_new(&config.database)?;
+
+            if !config.retention.is_enabled() {
+                log::info!("no retention settings; only vacuuming");
+            }
+
+            let stats = prune(&database, &config.retention).await?;
+
+            println!(
+                "Pruned {} links, {} observations, and {} collections.",
+                stats.n_links,
+                stats.n_observations,
+                stats.n_collections
+            );
+
+            if let Some(x) = &config.snapshots {
+                let n_snapshots = prune_snapshots(x, &database).await?;
+
+                println!("Pruned {n_snapshots} snapshots.");
+            }
+
+            Ok(())
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
@@ -195,11 +628,66 @@ async fn main() {
 
     env_logger::Builder::new().filter_level(max_level).init();
 
-    match process(&args).await {
+    match run(&args).await {
         Ok(_) => std::process::exit(0),
         Err(x) => {
-            log::error!("{x}");
+            log::error!("{x:#}");
             std::process::exit(1);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::Secret;
+
+    #[test]
+    fn page_changes_are_classified() {
+        let config = |pages: &[(&str, &str)]| -> Config {
+            let mut toml = "database = \"jobs.db\"\n".to_string();
+
+            for (name, extract) in pages {
+                toml.push_str(&format!(
+                    "[[page]]\nname = {name:?}\n\
+                     url = \"https://example.com/\"\n\
+                     extract = {extract:?}\n"
+                ));
+            }
+
+            toml::from_str(&toml).unwrap()
+        };
+        let old = config(&[("a", "a"), ("b", "a"), ("c", "a")]);
+        let new = config(&[("d", "a"), ("a", "a"), ("b", "li > a")]);
+
+        assert_eq!(
+            page_changes(&old, &new),
+            [
+                PageChange::Added("d"),
+                PageChange::Changed("b"),
+                PageChange::Removed("c")
+            ]
+        );
+        assert!(page_changes(&old, &old).is_empty());
+
+        // Secrets are left out of debug output but still compared.
+        //
+        let with_token = |token: &str| {
+            let mut config = old.clone();
+
+            config.page[0].request.headers.insert(
+                "Authorization".to_string(),
+                Secret::from(token.to_string()),
+            );
+            config
+        };
+
+        assert_eq!(
+            page_changes(&with_token("a"), &with_token("b")),
+            [PageChange::Changed("a")]
+        );
+        assert!(
+            page_changes(&with_token("a"), &with_token("a")).is_empty()
+        );
+    }
+}
@@ -1,12 +1,3 @@
-PRAGMA foreign_keys = ON;
-PRAGMA journal_mode = WAL;
-PRAGMA synchronous = NORMAL;
-PRAGMA auto_vacuum = INCREMENTAL;
-PRAGMA temp_store = MEMORY;
-PRAGMA page_size = 4096;
-
-BEGIN;
-
 CREATE TABLE IF NOT EXISTS pages (
     id INTEGER PRIMARY KEY,
     url TEXT,
@@ -46,5 +37,3 @@ CREATE INDEX IF NOT EXISTS links_collections_collection_idx
     ON links_collections (collection_id);
 CREATE INDEX IF NOT EXISTS links_collections_link_idx
     ON links_collections (link_id);
-
-COMMIT;
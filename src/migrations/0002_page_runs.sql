@@ -0,0 +1,17 @@
+CREATE TABLE page_runs (
+    id INTEGER PRIMARY KEY,
+    collection_id INTEGER REFERENCES collections (id),
+    page_id INTEGER REFERENCES pages (id),
+    timestamp DATETIME,
+    http_status INTEGER,
+    duration_ms INTEGER,
+    n_bytes INTEGER,
+    n_links INTEGER,
+    n_new_links INTEGER,
+    error TEXT
+);
+
+CREATE INDEX page_runs_collection_idx ON page_runs (collection_id);
+CREATE INDEX page_runs_page_idx ON page_runs (page_id);
+
+ALTER TABLE collections ADD COLUMN n_failed_pages INTEGER;
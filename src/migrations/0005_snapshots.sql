@@ -0,0 +1,10 @@
+CREATE TABLE snapshots (
+    id INTEGER PRIMARY KEY,
+    collection_id INTEGER REFERENCES collections (id),
+    page_id INTEGER REFERENCES pages (id),
+    timestamp DATETIME,
+    body BLOB
+);
+
+CREATE UNIQUE INDEX snapshots_collection_page_idx
+    ON snapshots (collection_id, page_id);
@@ -0,0 +1 @@
+ALTER TABLE pages ADD COLUMN baselined INTEGER NOT NULL DEFAULT 0;
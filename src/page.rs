@@ -1,16 +1,41 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use libxml::{parser, tree::document, xpath};
+use reqwest::header::{
+    ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+};
 use scraper::{selector::ToCss, ElementRef, Html, Selector};
 use serde::{Deserialize, Deserializer};
+use sha2::{Digest, Sha256};
 use tokio_util::sync::CancellationToken;
 
-use crate::request;
+use crate::database::CacheValidators;
+use crate::request::{self, Clients, RequestOptions};
+use crate::secret;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Page {
     pub name: String,
+
+    #[serde(deserialize_with = "secret::deserialize_exposed")]
     pub url: String,
+
     pub extract: Extract,
+
+    /// Whether to record the first successful collection of this page
+    /// silently, without reporting its links as new.
+    #[serde(default = "default_baseline")]
+    pub baseline: bool,
+
+    /// Whether to request the page even if robots.txt disallows it.
+    #[serde(default)]
+    pub ignore_robots: bool,
+
+    #[serde(flatten)]
+    pub request: RequestOptions,
+}
+
+fn default_baseline() -> bool {
+    true
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -44,6 +69,24 @@ pub enum Extract {
         #[serde(rename = "text-path")]
         text: XPath,
     },
+    /// Links from the items of an array in a JSON document.  The
+    /// array, and the href and text of each item, are located with JSON
+    /// pointers (RFC 6901).
+    Json {
+        #[serde(rename = "json")]
+        items: String,
+
+        #[serde(rename = "href-pointer")]
+        href: String,
+
+        #[serde(rename = "text-pointer")]
+        text: String,
+
+        /// Prepended to each href, for APIs that return IDs or paths
+        /// instead of URLs.
+        #[serde(rename = "href-prefix", default)]
+        href_prefix: String,
+    },
 }
 
 impl std::fmt::Display for Extract {
@@ -87,6 +130,22 @@ impl std::fmt::Display for Extract {
                     container, href, text
                 )
             }
+            Extract::Json {
+                items,
+                href,
+                text,
+                href_prefix,
+            } => {
+                write!(
+                    f,
+                    "JSON {{ \
+                     items = {items:?}, \
+                     href = {href:?}, \
+                     text = {text:?}, \
+                     href_prefix = {href_prefix:?} \
+                     }}"
+                )
+            }
         }
     }
 }
@@ -131,16 +190,149 @@ where
     Selector::parse(&selector_str).map_err(serde::de::Error::custom)
 }
 
+/// The response to a page request.
+#[derive(Debug)]
+pub struct Fetched {
+    pub status: u16,
+    pub body: String,
+    pub validators: CacheValidators,
+}
+
 impl Page {
-    pub async fn request(
+    /// Requests the page.  Responses are returned regardless of their
+    /// status.  The request is conditional on `validators`, so the
+    /// response status is 304 (Not Modified) if the page hasn't changed
+    /// since they were recorded.
+    pub async fn fetch(
         &self,
+        validators: &CacheValidators,
+        clients: &Clients,
         cancellation_token: CancellationToken,
-    ) -> Result<Vec<Link>> {
-        let body = request::get(&self.url, cancellation_token)
-            .await?
-            .text()
-            .await?;
+    ) -> Result<Fetched> {
+        let mut options = self.request.clone();
+
+        if let Some(x) = &validators.etag {
+            options
+                .headers
+                .insert(IF_NONE_MATCH.to_string(), x.clone().into());
+        }
+
+        if let Some(x) = &validators.last_modified {
+            options.headers.insert(
+                IF_MODIFIED_SINCE.to_string(),
+                x.clone().into(),
+            );
+        }
+
+        let response = request::send(
+            &self.url,
+            &options,
+            clients,
+            cancellation_token,
+        )
+        .await?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|x| x.to_str().ok())
+                .map(str::to_string)
+        };
+        let validators = CacheValidators {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        };
+
+        Ok(Fetched {
+            status: response.status().as_u16(),
+            body: response.text().await?,
+            validators,
+        })
+    }
+
+    /// Returns a hash of the settings of the page, including the
+    /// values of secrets, which are left out of debug output.  Pages
+    /// with the same fingerprint are requested and extracted alike.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+
+        // Selectors don't implement `PartialEq`, so the settings are
+        // hashed by their debug representation.
+        //
+        hasher.update(format!("{self:?}"));
+
+        for secret in self.request.secrets() {
+            hasher.update([0]);
+            hasher.update(secret.expose());
+        }
+
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Returns a hash of the elements of the body that links are
+    /// extracted from, i.e., the elements that the selector or the
+    /// container matches, or the links themselves for JSON.  Changes
+    /// elsewhere on the page, such as timestamps or tokens, don't change
+    /// the hash.
+    pub fn content_hash(&self, body: &str) -> Result<String> {
+        let mut hasher = Sha256::new();
+
+        match &self.extract {
+            Extract::CSSPlain(selector)
+            | Extract::CSSDetailed {
+                container: selector,
+                ..
+            } => {
+                for element in
+                    Html::parse_fragment(body).select(selector)
+                {
+                    hasher.update(element.html());
+                }
+            }
+            Extract::XPathPlain(XPath(expr))
+            | Extract::XPathDetailed {
+                container: XPath(expr),
+                ..
+            } => {
+                let html = parser::Parser::default_html()
+                    .parse_string(body)?;
+                let nodes = xpath::Context::new(&html)
+                    .map_err(|()| anyhow!("XPath context"))?
+                    .findnodes(expr, None)
+                    .map_err(|()| {
+                        anyhow!("XPath findnodes: {expr:?}")
+                    })?;
+
+                for node in nodes {
+                    hasher.update(html.node_to_string(&node));
+                }
+            }
+            Extract::Json {
+                items,
+                href,
+                text,
+                href_prefix,
+            } => {
+                // Only the links are hashed because the items often have
+                // fields that change on every request, e.g., the time at
+                // which a posting was last updated.
+                //
+                for link in
+                    json_links(body, items, href, text, href_prefix)?
+                {
+                    hasher.update(link.href);
+                    hasher.update([0]);
+                    hasher.update(link.text);
+                    hasher.update([0]);
+                }
+            }
+        }
+
+        Ok(format!("{:x}", hasher.finalize()))
+    }
 
+    /// Extracts the links from the body of the page.
+    pub fn extract(&self, body: &str) -> Result<Vec<Link>> {
         match &self.extract {
             Extract::CSSPlain(selector) => {
                 log::debug!(
@@ -149,7 +341,7 @@ impl Page {
                     selector.to_css_string()
                 );
 
-                Ok(Html::parse_fragment(&body)
+                Ok(Html::parse_fragment(body)
                     .select(selector)
                     .map(Link::from)
                     .collect())
@@ -171,7 +363,7 @@ impl Page {
                     text_tag.to_css_string()
                 );
 
-                Ok(Html::parse_fragment(&body)
+                Ok(Html::parse_fragment(body)
                     .select(container)
                     .map(|root| {
                         let href = root
@@ -203,7 +395,7 @@ impl Page {
                 );
 
                 let html = parser::Parser::default_html()
-                    .parse_string(&body)?;
+                    .parse_string(body)?;
                 let nodes = xpath::Context::new(&html)
                     .map_err(|()| anyhow!("XPath context"))?
                     .findnodes(expr, None);
@@ -231,7 +423,7 @@ impl Page {
                 );
 
                 let html = parser::Parser::default_html()
-                    .parse_string(&body)?;
+                    .parse_string(body)?;
                 let mut ctx = xpath::Context::new(&html)
                     .map_err(|()| anyhow!("XPath context"))?;
                 let nodes = ctx.findnodes(container, None);
@@ -258,10 +450,78 @@ impl Page {
                     })
                     .collect())
             }
+            Extract::Json {
+                items,
+                href,
+                text,
+                href_prefix,
+            } => {
+                log::debug!(
+                    target: &self.name,
+                    "Json {{ \
+                     items: {items:?}, \
+                     href: {href:?}, \
+                     text: {text:?}, \
+                     href_prefix: {href_prefix:?} \
+                     }}"
+                );
+
+                json_links(body, items, href, text, href_prefix)
+            }
         }
     }
 }
 
+/// Returns the links of the items that `items` points to in the JSON
+/// document `body`.  Items without an `href` are skipped.
+fn json_links(
+    body: &str,
+    items: &str,
+    href: &str,
+    text: &str,
+    href_prefix: &str,
+) -> Result<Vec<Link>> {
+    let serde_json::Value::Array(items) = json_items(body, items)?
+    else {
+        bail!("JSON pointer {items:?}: not an array");
+    };
+
+    Ok(items
+        .iter()
+        .filter_map(|item| {
+            Some(Link {
+                href: format!(
+                    "{href_prefix}{}",
+                    json_string(item.pointer(href))?
+                ),
+                text: json_string(item.pointer(text))
+                    .unwrap_or_default(),
+            })
+        })
+        .collect())
+}
+
+/// Returns the value that `pointer` points to in the JSON document
+/// `body`.
+fn json_items(body: &str, pointer: &str) -> Result<serde_json::Value> {
+    let mut document: serde_json::Value = serde_json::from_str(body)?;
+
+    document
+        .pointer_mut(pointer)
+        .map(serde_json::Value::take)
+        .ok_or_else(|| anyhow!("JSON pointer {pointer:?}: not found"))
+}
+
+/// Returns a string or a number as a string, and `None` for anything
+/// else.
+fn json_string(value: Option<&serde_json::Value>) -> Option<String> {
+    match value {
+        Some(serde_json::Value::String(x)) => Some(x.clone()),
+        Some(serde_json::Value::Number(x)) => Some(x.to_string()),
+        _ => None,
+    }
+}
+
 #[derive(Debug)]
 pub struct Link {
     pub href: String,
@@ -0,0 +1,441 @@
+use anyhow::{anyhow, bail, Result};
+
+/// A built-in page definition for an applicant-tracking system.  Pages
+/// that use a preset set its parameters alongside `preset`, and each
+/// `{parameter}` in the definition is replaced with the page's value.
+struct Preset {
+    name: &'static str,
+    parameters: &'static [&'static str],
+    page: &'static str,
+}
+
+const PRESETS: &[Preset] = &[
+    Preset {
+        name: "greenhouse",
+        parameters: &["board"],
+        page: r#"
+            url = "https://boards-api.greenhouse.io/v1/boards/{board}/jobs"
+            extract = { json = "/jobs", href-pointer = "/absolute_url", text-pointer = "/title" }
+        "#,
+    },
+    Preset {
+        name: "lever",
+        parameters: &["board"],
+        page: r#"
+            url = "https://api.lever.co/v0/postings/{board}?mode=json"
+            extract = { json = "", href-pointer = "/hostedUrl", text-pointer = "/text" }
+        "#,
+    },
+    Preset {
+        name: "ashby",
+        parameters: &["board"],
+        page: r#"
+            url = "https://api.ashbyhq.com/posting-api/job-board/{board}"
+            extract = { json = "/jobs", href-pointer = "/jobUrl", text-pointer = "/title" }
+        "#,
+    },
+    Preset {
+        name: "smartrecruiters",
+        parameters: &["board"],
+        page: r#"
+            url = "https://api.smartrecruiters.com/v1/companies/{board}/postings"
+            extract = { json = "/content", href-pointer = "/id", text-pointer = "/name", href-prefix = "https://jobs.smartrecruiters.com/{board}/" }
+        "#,
+    },
+    Preset {
+        name: "personio",
+        parameters: &["board"],
+        page: r#"
+            url = "https://{board}.jobs.personio.de/search.json"
+            extract = { json = "", href-pointer = "/id", text-pointer = "/name", href-prefix = "https://{board}.jobs.personio.de/job/" }
+        "#,
+    },
+    // Workday only returns the first 20 postings of a search.
+    //
+    Preset {
+        name: "workday",
+        parameters: &["board", "instance", "site"],
+        page: r#"
+            url = "https://{board}.{instance}.myworkdayjobs.com/wday/cxs/{board}/{site}/jobs"
+            method = "POST"
+            headers = { Content-Type = "application/json" }
+            body = '{"appliedFacets": {}, "limit": 20, "offset": 0, "searchText": ""}'
+            extract = { json = "/jobPostings", href-pointer = "/externalPath", text-pointer = "/title", href-prefix = "https://{board}.{instance}.myworkdayjobs.com/{site}" }
+        "#,
+    },
+];
+
+/// Expands the `template` and `preset` keys of a `[[page]]` table.  Keys
+/// that the page sets take precedence over those of its template, and
+/// keys that the template sets take precedence over those of the
+/// preset.  Tables such as `headers` are replaced, not merged.
+///
+/// # Errors
+///
+/// This function returns an error if the template or the preset
+/// doesn't exist, or if a parameter of the preset is missing.
+pub fn expand(
+    page: &mut toml::Value,
+    templates: &toml::Table,
+) -> Result<()> {
+    let Some(page) = page.as_table_mut() else {
+        return Ok(());
+    };
+
+    if let Some(name) = page.remove("template") {
+        let template = name
+            .as_str()
+            .and_then(|x| templates.get(x))
+            .and_then(toml::Value::as_table)
+            .ok_or_else(|| anyhow!("unknown template {name}"))?;
+
+        merge(page, template);
+    }
+
+    if let Some(name) = page.remove("preset") {
+        let preset = PRESETS
+            .iter()
+            .find(|x| name.as_str() == Some(x.name))
+            .ok_or_else(|| anyhow!("unknown preset {name}"))?;
+        let mut variables = Vec::new();
+
+        for parameter in preset.parameters {
+            match page.remove(*parameter) {
+                Some(toml::Value::String(x)) => {
+                    variables.push((*parameter, x));
+                }
+                _ => bail!(
+                    "preset {:?} requires a string {parameter:?}",
+                    preset.name
+                ),
+            }
+        }
+
+        let mut defaults: toml::Value = toml::from_str(preset.page)
+            .expect("preset should be valid TOML");
+
+        substitute(&mut defaults, &variables);
+
+        if let toml::Value::Table(x) = &defaults {
+            merge(page, x);
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds the keys of `defaults` that `page` doesn't set.
+fn merge(page: &mut toml::Table, defaults: &toml::Table) {
+    for (key, value) in defaults {
+        if !page.contains_key(key) {
+            page.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Replaces `{name}` in every string of `value` with the value of the
+/// variable `name`.
+pub fn substitute(
+    value: &mut toml::Value,
+    variables: &[(&str, String)],
+) {
+    match value {
+        toml::Value::String(x) => {
+            for (name, y) in variables {
+                *x = x.replace(&format!("{{{name}}}"), y);
+            }
+        }
+        toml::Value::Table(table) => {
+            for (_, x) in table.iter_mut() {
+                substitute(x, variables);
+            }
+        }
+        toml::Value::Array(array) => {
+            for x in array {
+                substitute(x, variables);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::Page;
+
+    fn page(toml: &str, templates: &str) -> Result<Page> {
+        let mut value = toml::from_str(toml)?;
+
+        expand(&mut value, &toml::from_str(templates)?)?;
+
+        Ok(value.try_into()?)
+    }
+
+    fn links(page: &Page, body: &str) -> Vec<(String, String)> {
+        page.extract(body)
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.href, x.text))
+            .collect()
+    }
+
+    #[test]
+    fn presets_extract_fixtures() {
+        let greenhouse = page(
+            r#"
+                name = "Acme"
+                preset = "greenhouse"
+                board = "acme"
+            "#,
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(
+            greenhouse.url,
+            "https://boards-api.greenhouse.io/v1/boards/acme/jobs"
+        );
+        assert_eq!(
+            links(
+                &greenhouse,
+                include_str!("../test-site/greenhouse.json")
+            ),
+            [
+                (
+                    "https://boards.greenhouse.io/acme/jobs/4012"
+                        .into(),
+                    "Backend Engineer".into()
+                ),
+                (
+                    "https://boards.greenhouse.io/acme/jobs/4013"
+                        .into(),
+                    "Data Analyst".into()
+                ),
+            ]
+        );
+
+        let lever = page(
+            r#"
+                name = "Acme"
+                preset = "lever"
+                board = "acme"
+            "#,
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(
+            links(&lever, include_str!("../test-site/lever.json")),
+            [(
+                "https://jobs.lever.co/acme/5f0c1d2e".into(),
+                "Product Designer".into()
+            )]
+        );
+
+        let workday = page(
+            r#"
+                name = "Acme"
+                preset = "workday"
+                board = "acme"
+                instance = "wd5"
+                site = "External"
+            "#,
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(
+            workday.url,
+            "https://acme.wd5.myworkdayjobs.com/wday/cxs/acme/External/jobs"
+        );
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(
+                workday.request.body.as_ref().unwrap().expose()
+            )
+            .unwrap(),
+            serde_json::json!({
+                "appliedFacets": {},
+                "limit": 20,
+                "offset": 0,
+                "searchText": ""
+            })
+        );
+        assert_eq!(
+            links(&workday, include_str!("../test-site/workday.json")),
+            [(
+                "https://acme.wd5.myworkdayjobs.com/External\
+                 /job/Berlin/Site-Reliability-Engineer_R1234"
+                    .into(),
+                "Site Reliability Engineer".into()
+            )]
+        );
+
+        let ashby = page(
+            r#"
+                name = "Acme"
+                preset = "ashby"
+                board = "acme"
+            "#,
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(
+            links(&ashby, include_str!("../test-site/ashby.json")),
+            [(
+                "https://jobs.ashbyhq.com/acme\
+                 /2f4e6a1c-8b3d-4e5f-9a7b-0c1d2e3f4a5b"
+                    .into(),
+                "Platform Engineer".into()
+            )]
+        );
+
+        let smartrecruiters = page(
+            r#"
+                name = "Acme"
+                preset = "smartrecruiters"
+                board = "acme"
+            "#,
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(
+            links(
+                &smartrecruiters,
+                include_str!("../test-site/smartrecruiters.json")
+            ),
+            [(
+                "https://jobs.smartrecruiters.com/acme/744000012345678"
+                    .into(),
+                "Sales Manager".into()
+            )]
+        );
+
+        let personio = page(
+            r#"
+                name = "Acme"
+                preset = "personio"
+                board = "acme"
+            "#,
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(
+            personio.url,
+            "https://acme.jobs.personio.de/search.json"
+        );
+        assert_eq!(
+            links(
+                &personio,
+                include_str!("../test-site/personio.json")
+            ),
+            [(
+                "https://acme.jobs.personio.de/job/1234567".into(),
+                "Office Manager".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn json_items_without_href_are_skipped() {
+        let greenhouse = page(
+            r#"
+                name = "Acme"
+                preset = "greenhouse"
+                board = "acme"
+            "#,
+            "",
+        )
+        .unwrap();
+
+        assert_eq!(
+            links(
+                &greenhouse,
+                r#"{"jobs": [{"title": "Draft"}, {"absolute_url": "a"}]}"#
+            ),
+            [("a".into(), String::new())]
+        );
+    }
+
+    #[test]
+    fn json_hash_ignores_other_fields() {
+        let greenhouse = page(
+            r#"
+                name = "Acme"
+                preset = "greenhouse"
+                board = "acme"
+            "#,
+            "",
+        )
+        .unwrap();
+        let body = include_str!("../test-site/greenhouse.json");
+        let hash = greenhouse.content_hash(body).unwrap();
+
+        assert_eq!(
+            greenhouse
+                .content_hash(&body.replace("2026-09-30", "2026-10-03"))
+                .unwrap(),
+            hash
+        );
+        assert_ne!(
+            greenhouse
+                .content_hash(
+                    &body.replace("Data Analyst", "Data Scientist")
+                )
+                .unwrap(),
+            hash
+        );
+    }
+
+    #[test]
+    fn templates_are_overridden_by_pages() {
+        let templates = r#"
+            [acme]
+            preset = "greenhouse"
+            board = "acme"
+            user_agent = "Template"
+
+            [plain]
+            url = "https://example.com/jobs"
+            extract = "a"
+        "#;
+
+        let acme = page(
+            r#"
+                name = "Acme Europe"
+                template = "acme"
+                board = "acme-europe"
+            "#,
+            templates,
+        )
+        .unwrap();
+
+        assert_eq!(
+            acme.url,
+            "https://boards-api.greenhouse.io/v1/boards/acme-europe/jobs"
+        );
+        assert_eq!(
+            acme.request.user_agent.as_deref(),
+            Some("Template")
+        );
+
+        let plain = page(
+            r#"
+                name = "Plain"
+                template = "plain"
+                url = "https://example.com/careers"
+            "#,
+            templates,
+        )
+        .unwrap();
+
+        assert_eq!(plain.url, "https://example.com/careers");
+
+        assert!(page(r#"template = "none""#, templates).is_err());
+        assert!(page(r#"preset = "none""#, "").is_err());
+        assert!(page(r#"preset = "workday""#, "").is_err());
+    }
+}
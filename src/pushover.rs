@@ -2,12 +2,23 @@ use anyhow::{anyhow, Result};
 use serde::Deserialize;
 use tokio_util::sync::CancellationToken;
 
-use crate::request;
+use crate::request::{self, Clients, RetryOptions};
+use crate::secret::Secret;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Pushover {
-    pub token: String,
-    pub user: String,
+    pub token: Secret,
+    pub user: Secret,
+
+    /// Retry policy for notifications, independent of the one for
+    /// pages.
+    #[serde(flatten)]
+    pub retry: RetryOptions,
+
+    /// Send notifications directly instead of through the proxy that
+    /// pages are requested through.
+    #[serde(default)]
+    pub no_proxy: bool,
 }
 
 impl Pushover {
@@ -15,11 +26,12 @@ impl Pushover {
         &self,
         message: &str,
         title: Option<&str>,
+        clients: &Clients,
         cancellation_token: CancellationToken,
     ) -> Result<()> {
         let mut form_data = vec![
-            ("token", self.token.as_str()),
-            ("user", self.user.as_str()),
+            ("token", self.token.expose()),
+            ("user", self.user.expose()),
             ("message", message),
         ];
 
@@ -30,6 +42,8 @@ impl Pushover {
         let status_code = request::post(
             "https://api.pushover.net/1/messages.json",
             &form_data,
+            &self.retry,
+            clients,
             cancellation_token,
         )
         .await?
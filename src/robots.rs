@@ -0,0 +1,213 @@
+use reqwest::Url;
+use std::time::Duration;
+
+/// User agent that robots.txt rules are matched against if pages don't
+/// set one.
+pub const DEFAULT_USER_AGENT: &str = "kairos";
+
+/// The rules of a robots.txt file that apply to one user agent.
+#[derive(Debug, Default, Clone)]
+pub struct Rules {
+    rules: Vec<Rule>,
+    pub crawl_delay: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+#[derive(Debug, Default)]
+struct Group {
+    user_agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Rules {
+    /// Parses a robots.txt file and returns the rules of the group
+    /// that matches `user_agent` most specifically, or of the `*` group
+    /// if none does.  Groups for the same user agent are merged.
+    pub fn parse(text: &str, user_agent: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut in_rules = true;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let key = key.trim().to_lowercase();
+            let value = value.trim();
+
+            if key == "user-agent" {
+                if in_rules {
+                    groups.push(Group::default());
+                    in_rules = false;
+                }
+
+                if let Some(group) = groups.last_mut() {
+                    group.user_agents.push(value.to_lowercase());
+                }
+
+                continue;
+            }
+
+            // Lines before the first user-agent line belong to no
+            // group.
+            //
+            let Some(group) = groups.last_mut() else {
+                continue;
+            };
+
+            in_rules = true;
+
+            match key.as_str() {
+                "allow" | "disallow" if !value.is_empty() => {
+                    group.rules.push(Rule {
+                        allow: key == "allow",
+                        pattern: value.to_string(),
+                    });
+                }
+                "crawl-delay" => {
+                    group.crawl_delay =
+                        value.parse().ok().and_then(|x| {
+                            Duration::try_from_secs_f64(x).ok()
+                        });
+                }
+                _ => {}
+            }
+        }
+
+        let user_agent = user_agent.to_lowercase();
+        let best_match = groups
+            .iter()
+            .flat_map(|x| &x.user_agents)
+            .filter(|x| *x != "*" && user_agent.contains(x.as_str()))
+            .max_by_key(|x| x.len())
+            .cloned()
+            .unwrap_or_else(|| "*".to_string());
+
+        groups
+            .into_iter()
+            .filter(|x| x.user_agents.contains(&best_match))
+            .fold(Rules::default(), |mut rules, group| {
+                rules.rules.extend(group.rules);
+                rules.crawl_delay =
+                    rules.crawl_delay.or(group.crawl_delay);
+                rules
+            })
+    }
+
+    /// Checks whether `url` may be requested.  The longest matching
+    /// pattern decides, and `Allow` wins a tie.
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let path = match url.query() {
+            Some(x) => format!("{}?{x}", url.path()),
+            None => url.path().to_string(),
+        };
+
+        self.rules
+            .iter()
+            .filter(|x| matches(&x.pattern, &path))
+            .max_by_key(|x| (x.pattern.len(), x.allow))
+            .is_none_or(|x| x.allow)
+    }
+}
+
+/// Matches a robots.txt path pattern, in which `*` matches any
+/// sequence of characters and a trailing `$` anchors the end of the
+/// path.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, is_anchored) = match pattern.strip_suffix('$') {
+        Some(x) => (x, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or(""))
+    else {
+        return false;
+    };
+    let parts = parts.collect::<Vec<_>>();
+
+    for (i, part) in parts.iter().enumerate() {
+        if is_anchored && i + 1 == parts.len() {
+            return rest.ends_with(part);
+        }
+
+        match rest.find(part) {
+            Some(j) => rest = &rest[j + part.len()..],
+            None => return false,
+        }
+    }
+
+    !is_anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "\
+# Example
+User-agent: *
+Disallow: /private/
+Allow: /private/jobs
+Disallow: /*.pdf$
+
+User-agent: BadBot
+User-agent: Kairos
+Disallow: /
+Crawl-delay: 2.5
+";
+
+    fn is_allowed(rules: &Rules, path: &str) -> bool {
+        let url = Url::parse("https://example.com")
+            .and_then(|x| x.join(path))
+            .unwrap();
+
+        rules.is_allowed(&url)
+    }
+
+    #[test]
+    fn parse_picks_the_most_specific_group() {
+        let rules =
+            Rules::parse(ROBOTS, "Mozilla/5.0 (compatible; Kairos)");
+
+        assert!(!is_allowed(&rules, "/jobs"));
+        assert_eq!(
+            rules.crawl_delay,
+            Some(Duration::from_millis(2500))
+        );
+
+        let rules = Rules::parse(ROBOTS, "curl/8.0");
+
+        assert!(is_allowed(&rules, "/jobs"));
+        assert_eq!(rules.crawl_delay, None);
+    }
+
+    #[test]
+    fn longest_match_decides() {
+        let rules = Rules::parse(ROBOTS, "curl/8.0");
+
+        assert!(!is_allowed(&rules, "/private/"));
+        assert!(!is_allowed(&rules, "/private/team"));
+        assert!(is_allowed(&rules, "/private/jobs?page=2"));
+    }
+
+    #[test]
+    fn wildcards_match() {
+        let rules = Rules::parse(ROBOTS, "curl/8.0");
+
+        assert!(!is_allowed(&rules, "/files/handbook.pdf"));
+        assert!(is_allowed(&rules, "/files/handbook.pdf?download=1"));
+        assert!(matches("/*/jobs*", "/en/jobs/1"));
+        assert!(!matches("/*/jobs", "/jobs"));
+    }
+
+    #[test]
+    fn empty_file_allows_everything() {
+        assert!(is_allowed(&Rules::parse("", DEFAULT_USER_AGENT), "/"));
+    }
+}
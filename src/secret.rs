@@ -0,0 +1,181 @@
+use anyhow::anyhow;
+use serde::{Deserialize, Deserializer};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// A configuration value that may be a credential.  It is either
+/// written in the configuration or read from an environment variable
+/// or a file:
+///
+/// ```toml
+/// token = "7b1fvzmr2g4xkkzs8xvzjm5eud80zt"
+/// token = { env = "PUSHOVER_TOKEN" }
+/// token = { file = "/run/secrets/pushover-token" }
+/// ```
+///
+/// Relative paths are resolved against `$CREDENTIALS_DIRECTORY` if it is
+/// set, which is where systemd places the credentials of a service
+/// that uses `LoadCredential=`.  The value is left out of debug output.
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "Source")]
+pub struct Secret(String);
+
+#[derive(Deserialize)]
+#[serde(untagged, rename = "Secret")]
+enum Source {
+    Inline(String),
+    Env(Env),
+    File(File),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Env {
+    env: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    file: String,
+}
+
+impl TryFrom<Source> for Secret {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        source: Source,
+    ) -> std::result::Result<Self, Self::Error> {
+        match source {
+            Source::Inline(x) => Ok(Secret(x)),
+            Source::Env(Env { env }) => {
+                std::env::var(&env).map(Secret).map_err(|x| {
+                    anyhow!("environment variable {env:?}: {x}")
+                })
+            }
+            Source::File(File { file }) => {
+                let file = file_path(
+                    &file,
+                    std::env::var_os("CREDENTIALS_DIRECTORY")
+                        .as_deref(),
+                );
+
+                std::fs::read_to_string(&file)
+                    .map(|x| {
+                        Secret(
+                            x.trim_end_matches(['\r', '\n'])
+                                .to_string(),
+                        )
+                    })
+                    .map_err(|x| anyhow!("secret file {file:?}: {x}"))
+            }
+        }
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret(value)
+    }
+}
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret(..)")
+    }
+}
+
+/// Deserializes a value that can be written like a [`Secret`] but isn't
+/// left out of debug output, such as a URL with an API key in its query
+/// string.
+pub fn deserialize_exposed<'de, D, T>(
+    deserializer: D,
+) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: From<String>,
+{
+    Secret::deserialize(deserializer).map(|x| T::from(x.0))
+}
+
+fn file_path(
+    path: &str,
+    credentials_directory: Option<&OsStr>,
+) -> PathBuf {
+    match credentials_directory {
+        Some(x) => Path::new(x).join(path),
+        None => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indexmap::IndexMap;
+
+    #[derive(Deserialize)]
+    struct Values {
+        secret: Secret,
+        map: IndexMap<String, Secret>,
+    }
+
+    #[test]
+    fn secret_reads_env_and_file() {
+        let path = std::env::temp_dir()
+            .join(format!("kairos-secret-{}", std::process::id()));
+
+        std::fs::write(&path, "hunter2\n").unwrap();
+
+        let values: Values = toml::from_str(&format!(
+            "secret = {{ env = \"PATH\" }}\n\
+             map = {{ a = {{ file = {path:?} }}, file = \"cv\" }}\n"
+        ))
+        .unwrap();
+
+        assert_eq!(
+            values.secret.expose(),
+            std::env::var("PATH").unwrap()
+        );
+        assert_eq!(values.map["a"].expose(), "hunter2");
+        assert_eq!(values.map["file"].expose(), "cv");
+
+        assert!(toml::from_str::<Values>(
+            "secret = { env = \"KAIROS_UNSET\" }\nmap = {}"
+        )
+        .is_err());
+        assert!(toml::from_str::<Values>(
+            "secret = { env = \"PATH\", file = \"x\" }\nmap = {}"
+        )
+        .is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_path_uses_credentials_directory() {
+        let dir = Some(OsStr::new("/run/credentials/kairos.service"));
+
+        assert_eq!(
+            file_path("token", dir),
+            Path::new("/run/credentials/kairos.service/token")
+        );
+        assert_eq!(
+            file_path("/etc/token", dir),
+            Path::new("/etc/token")
+        );
+        assert_eq!(file_path("token", None), Path::new("token"));
+    }
+
+    #[test]
+    fn secret_is_redacted() {
+        let secret = Secret("hunter2".to_string());
+
+        assert!(!format!("{secret:?}").contains("hunter2"));
+    }
+}
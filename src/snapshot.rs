@@ -0,0 +1,392 @@
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::config::Config;
+use crate::database::Database;
+
+/// Settings for storing the body of every fetched page, compressed,
+/// so that extracts can be tested against past versions of a page.
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct Snapshots {
+    /// Store snapshots as files in this directory instead of in the
+    /// database.
+    pub directory: Option<PathBuf>,
+
+    /// Delete snapshots older than this many days.
+    pub keep_days: Option<u64>,
+}
+
+impl Snapshots {
+    pub async fn store(
+        &self,
+        database: &Database,
+        collection_id: i64,
+        page_id: i64,
+        body: &str,
+    ) -> Result<()> {
+        let mut encoder =
+            GzEncoder::new(Vec::new(), Compression::default());
+
+        encoder.write_all(body.as_bytes())?;
+
+        let compressed = encoder.finish()?;
+
+        match &self.directory {
+            None => {
+                database
+                    .add_snapshot(collection_id, page_id, compressed)
+                    .await
+            }
+            Some(directory) => {
+                let path = file_path(directory, collection_id, page_id);
+
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent)
+                        .await
+                        .with_context(|| {
+                            format!("snapshot: {parent:?}")
+                        })?;
+                }
+
+                tokio::fs::write(&path, compressed)
+                    .await
+                    .with_context(|| format!("snapshot: {path:?}"))
+            }
+        }
+    }
+
+    /// Returns the body of the page fetched in the collection, if a
+    /// snapshot of it was stored.
+    pub async fn load(
+        &self,
+        database: &Database,
+        collection_id: i64,
+        page_id: i64,
+    ) -> Result<Option<String>> {
+        let compressed = match &self.directory {
+            None => database.snapshot(collection_id, page_id).await?,
+            Some(directory) => {
+                let path = file_path(directory, collection_id, page_id);
+
+                match tokio::fs::read(&path).await {
+                    Ok(x) => Some(x),
+                    Err(x)
+                        if x.kind() == std::io::ErrorKind::NotFound =>
+                    {
+                        None
+                    }
+                    Err(x) => {
+                        return Err(x).with_context(|| {
+                            format!("snapshot: {path:?}")
+                        });
+                    }
+                }
+            }
+        };
+
+        let Some(compressed) = compressed else {
+            return Ok(None);
+        };
+        let mut body = String::new();
+
+        GzDecoder::new(compressed.as_slice())
+            .read_to_string(&mut body)
+            .context("snapshot: decompress")?;
+
+        Ok(Some(body))
+    }
+
+    /// Deletes snapshots older than `keep_days` and returns how many
+    /// were deleted.  Snapshot directories are deleted per collection.
+    pub async fn prune(&self, database: &Database) -> Result<u64> {
+        let Some(days) = self.keep_days else {
+            return Ok(0);
+        };
+
+        let Some(directory) = &self.directory else {
+            return database.delete_snapshots(days).await;
+        };
+
+        let cutoff = SystemTime::now()
+            - Duration::from_secs(days * 24 * 60 * 60);
+        let mut n_snapshots = 0;
+        let mut entries = match tokio::fs::read_dir(directory).await {
+            Ok(x) => x,
+            Err(x) if x.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(0);
+            }
+            Err(x) => {
+                return Err(x).with_context(|| {
+                    format!("snapshot: {directory:?}")
+                });
+            }
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let is_collection = entry.file_type().await?.is_dir()
+                && path
+                    .file_name()
+                    .and_then(|x| x.to_str())
+                    .is_some_and(|x| x.parse::<i64>().is_ok());
+
+            if !is_collection
+                || entry.metadata().await?.modified()? >= cutoff
+            {
+                continue;
+            }
+
+            let mut files = tokio::fs::read_dir(&path).await?;
+
+            while files.next_entry().await?.is_some() {
+                n_snapshots += 1;
+            }
+
+            tokio::fs::remove_dir_all(&path)
+                .await
+                .with_context(|| format!("snapshot: {path:?}"))?;
+        }
+
+        Ok(n_snapshots)
+    }
+}
+
+fn file_path(
+    directory: &Path,
+    collection_id: i64,
+    page_id: i64,
+) -> PathBuf {
+    directory
+        .join(collection_id.to_string())
+        .join(format!("{page_id}.html.gz"))
+}
+
+/// Extracts links with the configured extracts from the snapshots of
+/// the pages fetched in the collection, and prints them along with how
+/// they differ from the links that were observed at the time.  Links
+/// marked with `+` are only found now, and links marked with `-` were
+/// only observed then.
+///
+/// Snapshots are matched with configured pages by URL.
+pub async fn reextract(
+    config: &Config,
+    database: &Database,
+    collection_id: i64,
+) -> Result<()> {
+    let snapshots = config.snapshots.clone().unwrap_or_default();
+    let pages = database.collection_pages(collection_id).await?;
+
+    if pages.is_empty() {
+        log::warn!("collection {collection_id} has no page runs");
+    }
+
+    for (page_id, url) in pages {
+        let Some(body) =
+            snapshots.load(database, collection_id, page_id).await?
+        else {
+            log::warn!("no snapshot of {url:?}");
+            continue;
+        };
+
+        let observed = database
+            .observed_links(collection_id, page_id)
+            .await?
+            .into_iter()
+            .map(|x| (x.href, x.text))
+            .collect::<Vec<_>>();
+
+        for page in config.page.iter().filter(|x| x.url == url) {
+            let links = page
+                .extract(&body)?
+                .into_iter()
+                .map(|x| (x.href, x.text))
+                .collect::<Vec<_>>();
+
+            println!(
+                "{}: {} links ({} observed in collection {collection_id})",
+                page.name,
+                links.len(),
+                observed.len()
+            );
+
+            for (marker, (href, text)) in diff(&links, &observed) {
+                println!("{marker} {href:?} {text:?}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the links found now, marked with `+` unless they were
+/// observed then, followed by the links only observed then, marked
+/// with `-`.
+fn diff<'a>(
+    links: &'a [(String, String)],
+    observed: &'a [(String, String)],
+) -> Vec<(char, &'a (String, String))> {
+    let found = links.iter().collect::<HashSet<_>>();
+    let was_observed = observed.iter().collect::<HashSet<_>>();
+
+    links
+        .iter()
+        .map(|x| (if was_observed.contains(x) { ' ' } else { '+' }, x))
+        .chain(
+            observed
+                .iter()
+                .filter(|x| !found.contains(x))
+                .map(|x| ('-', x)),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::Extract;
+    use scraper::selector::Selector;
+
+    const BODY: &str = "<a href=\"/jobs/1\">Engineer</a>";
+
+    async fn database_with_page() -> (Database, i64, i64) {
+        let db = Database::try_new(":memory:").unwrap();
+        let ex = Extract::CSSPlain(Selector::parse("a").unwrap());
+        let page_id = db.add_page("http://foo.bar", &ex).await.unwrap();
+        let collection_id = db.start_collection().await.unwrap();
+
+        (db, collection_id, page_id)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn snapshots_round_trip_in_database() {
+        let (db, collection_id, page_id) = database_with_page().await;
+        let snapshots = Snapshots::default();
+
+        snapshots
+            .store(&db, collection_id, page_id, BODY)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            snapshots.load(&db, collection_id, page_id).await.unwrap(),
+            Some(BODY.to_string())
+        );
+        assert_eq!(
+            snapshots
+                .load(&db, collection_id + 1, page_id)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            &db.snapshot(collection_id, page_id)
+                .await
+                .unwrap()
+                .unwrap()[..2],
+            [0x1f, 0x8b]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn snapshots_round_trip_in_directory() {
+        let (db, collection_id, page_id) = database_with_page().await;
+        let directory = std::env::temp_dir()
+            .join(format!("kairos-snapshots-{}", std::process::id()));
+        let snapshots = Snapshots {
+            directory: Some(directory.clone()),
+            keep_days: None,
+        };
+
+        snapshots
+            .store(&db, collection_id, page_id, BODY)
+            .await
+            .unwrap();
+
+        let loaded =
+            snapshots.load(&db, collection_id, page_id).await.unwrap();
+        let compressed = std::fs::read(file_path(
+            &directory,
+            collection_id,
+            page_id,
+        ))
+        .unwrap();
+        let missing = snapshots
+            .load(&db, collection_id + 1, page_id)
+            .await
+            .unwrap();
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(loaded, Some(BODY.to_string()));
+        assert_eq!(&compressed[..2], [0x1f, 0x8b]);
+        assert_eq!(missing, None);
+        assert!(db
+            .snapshot(collection_id, page_id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn prune_deletes_old_collection_directories() {
+        let (db, _, page_id) = database_with_page().await;
+        let directory = std::env::temp_dir().join(format!(
+            "kairos-snapshots-prune-{}",
+            std::process::id()
+        ));
+        let snapshots = Snapshots {
+            directory: Some(directory.clone()),
+            keep_days: Some(1),
+        };
+
+        for collection_id in [1, 2] {
+            snapshots
+                .store(&db, collection_id, page_id, BODY)
+                .await
+                .unwrap();
+        }
+
+        std::fs::create_dir_all(directory.join("other")).unwrap();
+
+        for name in ["1", "other"] {
+            std::fs::File::open(directory.join(name))
+                .unwrap()
+                .set_modified(
+                    SystemTime::now()
+                        - Duration::from_secs(2 * 24 * 60 * 60),
+                )
+                .unwrap();
+        }
+
+        let n_snapshots = snapshots.prune(&db).await.unwrap();
+        let remaining =
+            ["1", "2", "other"].map(|x| directory.join(x).exists());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(n_snapshots, 1);
+        assert_eq!(remaining, [false, true, true]);
+    }
+
+    #[test]
+    fn diff_marks_found_and_missing_links() {
+        let link = |x: &str| (x.to_string(), x.to_string());
+        let links = [link("/a"), link("/b")];
+        let observed = [link("/b"), link("/c")];
+
+        assert_eq!(
+            diff(&links, &observed)
+                .into_iter()
+                .map(|(marker, (href, _))| (marker, href.as_str()))
+                .collect::<Vec<_>>(),
+            [('+', "/a"), (' ', "/b"), ('-', "/c")]
+        );
+    }
+}
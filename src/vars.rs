@@ -0,0 +1,163 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::preset::substitute;
+
+/// Expands a `[[page]]` table with `vars` into one page for each
+/// combination of the values of the variables.  Each `{variable}` in the
+/// page is replaced with its value.  Unless the name of the page refers
+/// to the variables, their values are appended to it in the order in
+/// which the variables are declared.
+///
+/// # Errors
+///
+/// This function returns an error if a variable has no values or isn't
+/// used in the URL of the page.
+pub fn expand(mut page: toml::Value) -> Result<Vec<toml::Value>> {
+    let Some(vars) = page.as_table_mut().and_then(|x| x.remove("vars"))
+    else {
+        return Ok(vec![page]);
+    };
+    let toml::Value::Table(vars) = vars else {
+        bail!("vars: expected a table");
+    };
+    let url =
+        page.get("url").and_then(toml::Value::as_str).unwrap_or("");
+    let mut combinations = vec![Vec::new()];
+
+    for (name, values) in &vars {
+        // Pages are identified by their URL and extract in the
+        // database, so the pages need different URLs to have separate
+        // histories.
+        //
+        if !url.contains(&format!("{{{name}}}")) {
+            bail!("variable {name:?} is not used in url");
+        }
+
+        let values = values
+            .as_array()
+            .filter(|x| !x.is_empty())
+            .ok_or_else(|| {
+                anyhow!("variable {name:?}: expected a non-empty array")
+            })?
+            .iter()
+            .map(|x| match x {
+                toml::Value::String(x) => Ok(x.clone()),
+                toml::Value::Integer(x) => Ok(x.to_string()),
+                _ => bail!("variable {name:?}: expected strings"),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |x| {
+                    let mut combination = combination.clone();
+
+                    combination.push((name.as_str(), x.clone()));
+                    combination
+                })
+            })
+            .collect();
+    }
+
+    let name = page.get("name").and_then(toml::Value::as_str);
+
+    Ok(combinations
+        .into_iter()
+        .map(|variables| {
+            let mut expanded = page.clone();
+
+            substitute(&mut expanded, &variables);
+
+            if let Some(name) = name
+                && expanded.get("name").and_then(toml::Value::as_str)
+                    == Some(name)
+            {
+                let values = variables
+                    .iter()
+                    .map(|(_, x)| x.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                expanded["name"] = format!("{name} ({values})").into();
+            }
+
+            expanded
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::Page;
+
+    #[test]
+    fn vars_expand_into_pages() {
+        let expanded = |toml: &str| -> Result<Vec<(String, String)>> {
+            Ok(expand(toml::from_str(toml)?)?
+                .into_iter()
+                .map(|x| {
+                    let page: Page = x.try_into().unwrap();
+
+                    (page.name, page.url)
+                })
+                .collect())
+        };
+
+        assert_eq!(
+            expanded(
+                r#"
+                    name = "Acme"
+                    url = "https://acme.example/jobs?loc={loc}&dept={dept}"
+                    extract = "a"
+                    vars = { loc = ["berlin", "london"], dept = [7] }
+                "#
+            )
+            .unwrap(),
+            [
+                (
+                    "Acme (berlin, 7)".into(),
+                    "https://acme.example/jobs?loc=berlin&dept=7".into()
+                ),
+                (
+                    "Acme (london, 7)".into(),
+                    "https://acme.example/jobs?loc=london&dept=7".into()
+                ),
+            ]
+        );
+        assert_eq!(
+            expanded(
+                r#"
+                    name = "Acme {loc}"
+                    url = "https://acme.example/{loc}"
+                    extract = "a"
+                    vars = { loc = ["berlin"] }
+                "#
+            )
+            .unwrap(),
+            [(
+                "Acme berlin".into(),
+                "https://acme.example/berlin".into()
+            )]
+        );
+        assert!(expanded(
+            r#"
+                    name = "Acme"
+                    url = "https://acme.example/"
+                    extract = "a"
+                    vars = { loc = ["berlin"] }
+                "#
+        )
+        .is_err());
+        assert!(expanded(
+            r#"
+                    name = "Acme"
+                    url = "https://acme.example/{loc}"
+                    extract = "a"
+                    vars = { loc = [] }
+                "#
+        )
+        .is_err());
+    }
+}
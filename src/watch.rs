@@ -0,0 +1,141 @@
+use anyhow::Result;
+use notify::{RecursiveMode, Watcher as _};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+
+/// How long the files of the configuration need to stay unchanged
+/// before it is reloaded.  Editors and `git checkout` can write several
+/// files, or the same file several times, in quick succession.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Watches the configuration file and the files that it includes.
+pub struct Watcher {
+    _watcher: notify::RecommendedWatcher,
+    receiver: mpsc::UnboundedReceiver<PathBuf>,
+}
+
+impl Watcher {
+    /// Starts watching the directories of the configuration file and of
+    /// the files that its `include` patterns match, so that files that
+    /// are added later are noticed too.
+    pub fn new(config: &Config) -> Result<Self> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(
+            move |event: notify::Result<notify::Event>| match event {
+                Ok(x) if !x.kind.is_access() => {
+                    for path in x.paths {
+                        let _ = sender.send(path);
+                    }
+                }
+                Ok(_) => {}
+                Err(x) => log::error!("watch: {x}"),
+            },
+        )?;
+
+        for (directory, recursive) in config.directories() {
+            let mode = if recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+
+            if let Err(x) = watcher.watch(&directory, mode) {
+                log::warn!("watch {directory:?}: {x}");
+            }
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            receiver,
+        })
+    }
+
+    /// Waits until a file of `config` changes and then stays unchanged
+    /// for a while.
+    pub async fn changed(&mut self, config: &Config) {
+        debounce(&mut self.receiver, config).await;
+    }
+}
+
+/// Waits until `receiver` reports a file of `config` and then reports
+/// none for [`DEBOUNCE`].  Other files, such as the database, are
+/// ignored.
+async fn debounce(
+    receiver: &mut mpsc::UnboundedReceiver<PathBuf>,
+    config: &Config,
+) {
+    let mut deadline = None;
+
+    loop {
+        let path = match deadline {
+            Some(x) => {
+                match tokio::time::timeout_at(x, receiver.recv()).await
+                {
+                    Ok(Some(x)) => x,
+                    _ => return,
+                }
+            }
+            None => match receiver.recv().await {
+                Some(x) => x,
+                None => return std::future::pending().await,
+            },
+        };
+
+        if config.is_source(&path) {
+            log::debug!("{path:?} changed");
+            deadline = Some(tokio::time::Instant::now() + DEBOUNCE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Instant};
+
+    #[tokio::test]
+    async fn debounce_ignores_other_files() {
+        let mut config: Config = toml::from_str(
+            "database = \"jobs.db\"\npage = []\n\
+             include = [\"pages.d/*.toml\"]\n",
+        )
+        .unwrap();
+
+        config
+            .sources
+            .push(PathBuf::from("/etc/kairos/config.toml"));
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+
+        sender.send(PathBuf::from("/etc/kairos/jobs.db")).unwrap();
+
+        assert!(timeout(
+            DEBOUNCE * 2,
+            debounce(&mut receiver, &config)
+        )
+        .await
+        .is_err());
+
+        let start = Instant::now();
+
+        sender
+            .send(PathBuf::from("/etc/kairos/pages.d/1.toml"))
+            .unwrap();
+        tokio::spawn(async move {
+            for _ in 0..10 {
+                tokio::time::sleep(DEBOUNCE / 5).await;
+                let _ =
+                    sender.send(PathBuf::from("/etc/kairos/jobs.db"));
+            }
+        });
+
+        timeout(DEBOUNCE * 2, debounce(&mut receiver, &config))
+            .await
+            .unwrap();
+
+        assert!(start.elapsed() < DEBOUNCE * 2);
+    }
+}